@@ -1,12 +1,14 @@
-use gpui::*;
-use ropey::Rope;
-use std::path::PathBuf;
-
-#[derive(Clone, Debug)]
-enum Edit {
-    Insert { pos: usize, text: String },
-    Remove { pos: usize, text: String },
-}
+use crate::components::disk_state::{DiskChange, DiskFingerprint};
+use crate::components::encoding::TextEncoding;
+use crate::components::history::{Edit, EditKind, History, Transaction, TransactionId};
+use crate::components::line_ending::{self, LineEnding};
+use crate::components::save::{self, SaveError};
+use crate::components::{ByteOffset, CharOffset, OffsetUtf16, Point, PointUtf16, Selection};
+use encoding_rs::Encoding;
+use ropey::{Rope, RopeSlice};
+use similar::{DiffTag, TextDiff};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 
 /// A Buffer represents a file's content and state
 pub struct Buffer {
@@ -14,12 +16,22 @@ pub struct Buffer {
     rope: Rope,
     /// Path to the file on disk (None for new/unsaved buffers)
     file_path: Option<PathBuf>,
-    /// Whether the buffer has unsaved changes
-    is_dirty: bool,
-    /// Undo history
-    undo_stack: Vec<Edit>,
-    /// Redo history
-    redo_stack: Vec<Edit>,
+    /// Whether the line ending was changed since the last save, which isn't an undoable
+    /// edit
+    line_ending_changed: bool,
+    /// Counts changes to the text or the undo tree, so views can tell when what they
+    /// worked out from them is stale
+    version: usize,
+    /// Undo and redo history
+    history: History,
+    /// The line ending written on save; the rope itself only holds `\n`
+    line_ending: LineEnding,
+    /// The encoding the file was read with and is written with
+    encoding: TextEncoding,
+    /// Whether saving keeps the previous version of the file as `file~`
+    keep_backup: bool,
+    /// The version of the file the buffer was last loaded from or saved to
+    disk_fingerprint: Option<DiskFingerprint>,
 }
 
 impl Buffer {
@@ -28,67 +40,223 @@ impl Buffer {
         Self {
             rope: Rope::new(),
             file_path: None,
-            is_dirty: false,
-            undo_stack: Vec::new(),
-            redo_stack: Vec::new(),
+            line_ending_changed: false,
+            version: 0,
+            history: History::new(),
+            line_ending: LineEnding::default(),
+            encoding: TextEncoding::default(),
+            keep_backup: false,
+            disk_fingerprint: None,
         }
     }
 
     /// Create a buffer with initial text
     pub fn with_text(text: impl Into<String>) -> Self {
+        let text = text.into();
         Self {
-            rope: Rope::from_str(&text.into()),
+            rope: Rope::from_str(&line_ending::normalize(&text)),
             file_path: None,
-            is_dirty: false,
-            undo_stack: Vec::new(),
-            redo_stack: Vec::new(),
+            line_ending_changed: false,
+            version: 0,
+            history: History::new(),
+            line_ending: LineEnding::detect(&text),
+            encoding: TextEncoding::default(),
+            keep_backup: false,
+            disk_fingerprint: None,
         }
     }
 
-    /// Load a buffer from a file
+    /// Load a buffer from a file, detecting its encoding
     pub fn from_file(path: PathBuf) -> std::io::Result<Self> {
-        let content = std::fs::read_to_string(&path)?;
-        Ok(Self {
-            rope: Rope::from_str(&content),
-            file_path: Some(path),
-            is_dirty: false,
-            undo_stack: Vec::new(),
-            redo_stack: Vec::new(),
-        })
+        let mut buffer = Self::new();
+        buffer.load_file(path)?;
+        Ok(buffer)
     }
 
     /// Save the buffer to its file
-    pub fn save(&mut self) -> std::io::Result<()> {
-        if let Some(path) = &self.file_path {
-            std::fs::write(path, self.rope.to_string())?;
-            self.is_dirty = false;
-            Ok(())
-        } else {
-            Err(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "No file path set",
-            ))
+    pub fn save(&mut self) -> Result<(), SaveError> {
+        self.save_with_encoding(self.encoding)
+    }
+
+    /// Save the buffer to its file in another encoding, which is kept for later saves
+    pub fn save_with_encoding(&mut self, encoding: TextEncoding) -> Result<(), SaveError> {
+        let Some(path) = self.file_path.clone() else {
+            return Err(SaveError::NoPath);
+        };
+        // Don't overwrite changes made outside the editor without being told to
+        if self.disk_change() == DiskChange::Modified {
+            return Err(SaveError::ChangedOnDisk(path));
         }
+        let bytes = self.bytes_for_disk(encoding)?;
+        save::write_atomic(&path, &bytes, self.keep_backup)?;
+        self.encoding = encoding;
+        self.mark_saved();
+        self.update_fingerprint(&path, &bytes);
+        Ok(())
     }
 
     /// Save the buffer to a specific path
-    pub fn save_as(&mut self, path: PathBuf) -> std::io::Result<()> {
-        std::fs::write(&path, self.rope.to_string())?;
+    pub fn save_as(&mut self, path: PathBuf) -> Result<(), SaveError> {
+        let bytes = self.bytes_for_disk(self.encoding)?;
+        save::write_atomic(&path, &bytes, self.keep_backup)?;
+        self.update_fingerprint(&path, &bytes);
         self.file_path = Some(path);
-        self.is_dirty = false;
+        self.mark_saved();
         Ok(())
     }
 
+    /// Treat the current text as what's on disk
+    fn mark_saved(&mut self) {
+        self.history.mark_saved();
+        self.line_ending_changed = false;
+    }
+
+    fn update_fingerprint(&mut self, path: &Path, bytes: &[u8]) {
+        self.disk_fingerprint = std::fs::metadata(path)
+            .ok()
+            .map(|metadata| DiskFingerprint::new(bytes, &metadata));
+    }
+
+    /// Compare the file on disk with the version last loaded or saved. Only reads the file
+    /// when its modification time or size has changed.
+    pub fn disk_change(&mut self) -> DiskChange {
+        let (Some(path), Some(fingerprint)) = (&self.file_path, &self.disk_fingerprint) else {
+            return DiskChange::Unchanged;
+        };
+        match std::fs::metadata(path) {
+            Ok(metadata) if fingerprint.matches_metadata(&metadata) => DiskChange::Unchanged,
+            Ok(_) => match DiskFingerprint::read(path) {
+                Ok((_, current)) if current.matches_content(fingerprint) => {
+                    // Touched but not changed, so remember the new mtime
+                    self.disk_fingerprint = Some(current);
+                    DiskChange::Unchanged
+                }
+                Ok(_) => DiskChange::Modified,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => DiskChange::Deleted,
+                Err(_) => DiskChange::Unchanged,
+            },
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => DiskChange::Deleted,
+            Err(_) => DiskChange::Unchanged,
+        }
+    }
+
+    /// Treat the file on disk as the version the buffer is based on, so the next save
+    /// overwrites it
+    pub fn ignore_disk_changes(&mut self) {
+        if let Some(path) = &self.file_path {
+            self.disk_fingerprint = DiskFingerprint::read(path)
+                .ok()
+                .map(|(_, fingerprint)| fingerprint);
+        }
+    }
+
+    /// The file's text on disk, decoded and with line endings normalized like the buffer's
+    pub fn disk_text(&self) -> std::io::Result<String> {
+        let Some(path) = &self.file_path else {
+            return Ok(String::new());
+        };
+        let bytes = std::fs::read(path)?;
+        let encoding = TextEncoding::for_file(self.encoding.encoding, &bytes);
+        Ok(line_ending::normalize(&encoding.decode(&bytes)).into_owned())
+    }
+
+    /// Replace the buffer's text with the file on disk as one undoable transaction. Only
+    /// the lines that differ are replaced, and the selection is carried across them.
+    pub fn reload(&mut self, selection: &Selection) -> std::io::Result<Selection> {
+        let Some(path) = self.file_path.clone() else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "No file path set",
+            ));
+        };
+        let (bytes, fingerprint) = DiskFingerprint::read(&path)?;
+        let encoding = TextEncoding::for_file(self.encoding.encoding, &bytes);
+        let content = encoding.decode(&bytes);
+        let new_text = line_ending::normalize(&content);
+        let old_text = self.rope.to_string();
+
+        let diff = TextDiff::from_lines(old_text.as_str(), &*new_text);
+        let old_offsets = token_offsets(diff.old_slices());
+        let new_offsets = token_offsets(diff.new_slices());
+        let hunks = diff
+            .ops()
+            .iter()
+            .filter(|op| op.tag() != DiffTag::Equal)
+            .map(|op| {
+                let old = op.old_range();
+                let new = op.new_range();
+                (
+                    old_offsets[old.start]..old_offsets[old.end],
+                    &new_text[new_offsets[new.start]..new_offsets[new.end]],
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let map = |offset: ByteOffset| ByteOffset(map_offset(offset.0, &hunks));
+        let mut new_selection = Selection::new(map(selection.anchor), map(selection.head));
+
+        self.start_transaction(selection, EditKind::Other);
+        // Back to front, so earlier hunks' offsets stay valid
+        for (old, new) in hunks.iter().rev() {
+            if !old.is_empty() {
+                self.remove(ByteOffset(old.start)..ByteOffset(old.end));
+            }
+            if !new.is_empty() {
+                self.insert(ByteOffset(old.start), new);
+            }
+        }
+        new_selection.anchor = self.clip_offset(new_selection.anchor);
+        new_selection.head = self.clip_offset(new_selection.head);
+        self.end_transaction(&new_selection);
+
+        self.line_ending = LineEnding::detect(&content);
+        self.encoding = encoding;
+        self.mark_saved();
+        self.disk_fingerprint = Some(fingerprint);
+        Ok(new_selection)
+    }
+
     /// Get the buffer content as a string
     pub fn to_string(&self) -> String {
         self.rope.to_string()
     }
 
+    /// Get the content as it should be written, with the file's line endings
+    fn bytes_for_disk(&self, encoding: TextEncoding) -> Result<Vec<u8>, SaveError> {
+        encoding.encode(&self.line_ending.apply(&self.rope.to_string()))
+    }
+
+    /// Keep the previous version of the file as `file~` on each save
+    pub fn set_keep_backup(&mut self, keep_backup: bool) {
+        self.keep_backup = keep_backup;
+    }
+
+    pub fn encoding(&self) -> TextEncoding {
+        self.encoding
+    }
+
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    /// Change the line ending the file is written with on the next save
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        if line_ending != self.line_ending {
+            self.line_ending = line_ending;
+            self.line_ending_changed = true;
+        }
+    }
+
     /// Get the file path
     pub fn file_path(&self) -> Option<&PathBuf> {
         self.file_path.as_ref()
     }
 
+    /// Point the buffer at the file's new location after it was renamed or moved
+    pub fn set_file_path(&mut self, path: PathBuf) {
+        self.file_path = Some(path);
+    }
+
     /// Get the file name
     pub fn file_name(&self) -> Option<&str> {
         self.file_path
@@ -97,53 +265,170 @@ impl Buffer {
             .and_then(|n| n.to_str())
     }
 
-    /// Check if the buffer has unsaved changes
+    /// Check if the buffer has unsaved changes: its text isn't in the state last saved, or
+    /// its line ending was changed
     pub fn is_dirty(&self) -> bool {
-        self.is_dirty
+        self.line_ending_changed || !self.history.is_saved()
     }
 
-    /// Insert text at a position
-    pub fn insert(&mut self, pos: usize, text: &str) {
-        self.rope.insert(pos, text);
-        self.undo_stack.push(Edit::Insert {
+    /// Insert text at a position. The text should only use `\n` line endings.
+    pub fn insert(&mut self, pos: ByteOffset, text: &str) {
+        self.rope.insert(self.byte_to_char(pos).0, text);
+        self.record(Edit::Insert {
             pos,
             text: text.to_string(),
         });
-        self.redo_stack.clear(); // Clear redo stack on new edit
-        self.is_dirty = true;
     }
 
     /// Insert a character at a position
-    pub fn insert_char(&mut self, pos: usize, c: char) {
-        self.rope.insert_char(pos, c);
-        self.undo_stack.push(Edit::Insert {
+    pub fn insert_char(&mut self, pos: ByteOffset, c: char) {
+        self.rope.insert_char(self.byte_to_char(pos).0, c);
+        self.record(Edit::Insert {
             pos,
             text: c.to_string(),
         });
-        self.redo_stack.clear(); // Clear redo stack on new edit
-        self.is_dirty = true;
     }
 
     /// Remove a range of text
-    pub fn remove(&mut self, range: std::ops::Range<usize>) {
-        let removed_text = self.rope.slice(range.start..range.end).to_string();
-        self.rope.remove(range.clone());
-        self.undo_stack.push(Edit::Remove {
+    pub fn remove(&mut self, range: Range<ByteOffset>) {
+        let removed_text = self.slice(range.clone()).to_string();
+        self.remove_chars(range.clone());
+        self.record(Edit::Remove {
             pos: range.start,
             text: removed_text,
         });
-        self.redo_stack.clear(); // Clear redo stack on new edit
-        self.is_dirty = true;
     }
 
-    /// Get the length of the buffer in bytes
-    pub fn len_bytes(&self) -> usize {
-        self.rope.len_bytes()
+    fn record(&mut self, edit: Edit) {
+        self.history.push(edit);
+        self.version += 1;
     }
 
-    /// Get the length of the buffer in chars
-    pub fn len_chars(&self) -> usize {
-        self.rope.len_chars()
+    /// Group the following edits into one undo step, remembering the selection to restore.
+    /// Typing and deleting can merge with the step before when they quickly continue it.
+    pub fn start_transaction(&mut self, selection: &Selection, kind: EditKind) {
+        self.history.start_transaction(selection, kind);
+    }
+
+    /// Close the transaction started last, remembering the selection to restore on redo
+    pub fn end_transaction(&mut self, selection: &Selection) {
+        self.history.end_transaction(selection);
+    }
+
+    /// Start a new undo step with the next transaction, even one that could merge
+    pub fn finish_undo_group(&mut self) {
+        self.history.finish_group();
+    }
+
+    fn remove_chars(&mut self, range: Range<ByteOffset>) {
+        remove_bytes(&mut self.rope, range);
+    }
+
+    /// Get a slice of the buffer between two byte offsets
+    pub fn slice(&self, range: Range<ByteOffset>) -> RopeSlice<'_> {
+        self.rope.byte_slice(range.start.0..range.end.0)
+    }
+
+    /// Get the length of the buffer in bytes, which is also the offset of its end
+    pub fn len_bytes(&self) -> ByteOffset {
+        ByteOffset(self.rope.len_bytes())
+    }
+
+    /// Get the length of the buffer in chars, which is also the offset of its end
+    pub fn len_chars(&self) -> CharOffset {
+        CharOffset(self.rope.len_chars())
+    }
+
+    /// Get the contents of a row, excluding its line break
+    pub fn line(&self, row: u32) -> RopeSlice<'_> {
+        let line = self.rope.line(row as usize);
+        line.byte_slice(..line.len_bytes() - line_break_len(line))
+    }
+
+    /// Get the number of lines in the buffer, counting a trailing empty line
+    pub fn len_lines(&self) -> u32 {
+        self.rope.len_lines() as u32
+    }
+
+    /// Get the length of a row in bytes, excluding its line break
+    pub fn line_len(&self, row: u32) -> u32 {
+        let line = self.rope.line(row as usize);
+        (line.len_bytes() - line_break_len(line)) as u32
+    }
+
+    /// Get the point at the very end of the buffer
+    pub fn max_point(&self) -> Point {
+        self.offset_to_point(self.len_bytes())
+    }
+
+    /// Round a byte offset down to the nearest char boundary
+    pub fn clip_offset(&self, offset: ByteOffset) -> ByteOffset {
+        self.char_to_byte(self.byte_to_char(offset))
+    }
+
+    /// Convert a byte offset to a char offset
+    pub fn byte_to_char(&self, offset: ByteOffset) -> CharOffset {
+        CharOffset(self.rope.byte_to_char(offset.0.min(self.rope.len_bytes())))
+    }
+
+    /// Convert a char offset to a byte offset
+    pub fn char_to_byte(&self, offset: CharOffset) -> ByteOffset {
+        ByteOffset(self.rope.char_to_byte(offset.0.min(self.rope.len_chars())))
+    }
+
+    /// Convert a byte offset to a UTF-16 offset
+    pub fn offset_to_offset_utf16(&self, offset: ByteOffset) -> OffsetUtf16 {
+        OffsetUtf16(self.rope.char_to_utf16_cu(self.byte_to_char(offset).0))
+    }
+
+    /// Convert a UTF-16 offset to a byte offset, rounding down to a char boundary
+    pub fn offset_utf16_to_offset(&self, offset: OffsetUtf16) -> ByteOffset {
+        let offset = offset.0.min(self.rope.len_utf16_cu());
+        self.char_to_byte(CharOffset(self.rope.utf16_cu_to_char(offset)))
+    }
+
+    /// Convert a byte offset to a row and byte column
+    pub fn offset_to_point(&self, offset: ByteOffset) -> Point {
+        let offset = self.clip_offset(offset);
+        let row = self.rope.byte_to_line(offset.0);
+        let column = offset.0 - self.rope.line_to_byte(row);
+        Point::new(row as u32, column as u32)
+    }
+
+    /// Convert a row and byte column to a byte offset, clamping to the end of the row
+    pub fn point_to_offset(&self, point: Point) -> ByteOffset {
+        if point.row >= self.len_lines() {
+            return self.len_bytes();
+        }
+        let column = point.column.min(self.line_len(point.row));
+        let line_start = self.rope.line_to_byte(point.row as usize);
+        self.clip_offset(ByteOffset(line_start + column as usize))
+    }
+
+    /// Convert a byte-column point to a UTF-16 point, as language servers count columns
+    #[allow(dead_code)]
+    pub fn point_to_point_utf16(&self, point: Point) -> PointUtf16 {
+        let offset = self.point_to_offset(point);
+        let point = self.offset_to_point(offset);
+        let line_start = self.rope.line_to_char(point.row as usize);
+        let char_idx = self.byte_to_char(offset).0;
+        let column = self.rope.char_to_utf16_cu(char_idx) - self.rope.char_to_utf16_cu(line_start);
+        PointUtf16::new(point.row, column as u32)
+    }
+
+    /// Convert a UTF-16 point to a byte-column point, clamping to the end of the row
+    #[allow(dead_code)]
+    pub fn point_utf16_to_point(&self, point: PointUtf16) -> Point {
+        if point.row >= self.len_lines() {
+            return self.max_point();
+        }
+        let line_start = self.rope.line_to_char(point.row as usize);
+        let line_end = self.point_to_offset(Point::new(point.row, self.line_len(point.row)));
+        let line_end_utf16 = self.rope.char_to_utf16_cu(self.byte_to_char(line_end).0);
+        let utf16_idx =
+            (self.rope.char_to_utf16_cu(line_start) + point.column as usize).min(line_end_utf16);
+        let offset = self.char_to_byte(CharOffset(self.rope.utf16_cu_to_char(utf16_idx)));
+        self.offset_to_point(offset)
     }
 
     /// Get a reference to the underlying rope
@@ -151,74 +436,371 @@ impl Buffer {
         &self.rope
     }
 
-    /// Replace the entire buffer content
+    /// Replace the entire buffer content as one undoable transaction
     pub fn set_text(&mut self, text: impl Into<String>) {
-        self.rope = Rope::from_str(&text.into());
-        self.is_dirty = true;
+        let text = text.into();
+        let text = line_ending::normalize(&text);
+        let end = self.len_bytes();
+        self.start_transaction(&Selection::cursor(end), EditKind::Other);
+        if end.0 > 0 {
+            self.remove(ByteOffset(0)..end);
+        }
+        if !text.is_empty() {
+            self.insert(ByteOffset(0), &text);
+        }
+        self.end_transaction(&Selection::cursor(self.len_bytes()));
     }
 
     /// Load content from a file, replacing current content
     pub fn load_file(&mut self, path: PathBuf) -> std::io::Result<()> {
-        let content = std::fs::read_to_string(&path)?;
-        self.rope = Rope::from_str(&content);
-        self.file_path = Some(path);
-        self.is_dirty = false;
-        self.undo_stack.clear();
-        self.redo_stack.clear();
+        let (bytes, fingerprint) = DiskFingerprint::read(&path)?;
+        let (content, encoding) = TextEncoding::detect(&bytes);
+        self.set_loaded_text(path, &content, encoding);
+        self.disk_fingerprint = Some(fingerprint);
         Ok(())
     }
 
-    /// Undo the last edit
-    pub fn undo(&mut self) -> Option<usize> {
-        let edit = self.undo_stack.pop()?;
-
-        let cursor_pos = match &edit {
-            Edit::Insert { pos, text } => {
-                // Undo an insert by removing the text
-                let end = pos + text.len();
-                self.rope.remove(*pos..end);
-                *pos
-            }
-            Edit::Remove { pos, text } => {
-                // Undo a remove by inserting the text back
-                self.rope.insert(*pos, text);
-                *pos
-            }
+    /// Read the file again, decoding it with the given encoding instead of the detected one
+    pub fn reopen_with_encoding(&mut self, encoding: &'static Encoding) -> std::io::Result<()> {
+        let Some(path) = self.file_path.clone() else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "No file path set",
+            ));
         };
+        let (bytes, fingerprint) = DiskFingerprint::read(&path)?;
+        let encoding = TextEncoding::for_file(encoding, &bytes);
+        self.set_loaded_text(path, &encoding.decode(&bytes), encoding);
+        self.disk_fingerprint = Some(fingerprint);
+        Ok(())
+    }
 
-        self.redo_stack.push(edit);
-        Some(cursor_pos)
+    fn set_loaded_text(&mut self, path: PathBuf, content: &str, encoding: TextEncoding) {
+        self.rope = Rope::from_str(&line_ending::normalize(content));
+        self.line_ending = LineEnding::detect(content);
+        self.encoding = encoding;
+        self.file_path = Some(path);
+        self.line_ending_changed = false;
+        self.history.clear();
+        self.version += 1;
     }
 
-    /// Redo the last undone edit
-    pub fn redo(&mut self) -> Option<usize> {
-        let edit = self.redo_stack.pop()?;
+    /// Undo the last transaction, returning the selection from before it
+    pub fn undo(&mut self) -> Option<Selection> {
+        let transaction = self.history.undo()?;
+        revert_transaction(&mut self.rope, transaction);
+        self.version += 1;
+        Some(transaction.selection_before.clone())
+    }
+
+    /// Redo the last undone transaction, returning the selection from after it
+    pub fn redo(&mut self) -> Option<Selection> {
+        let transaction = self.history.redo()?;
+        apply_transaction(&mut self.rope, transaction);
+        self.version += 1;
+        Some(transaction.selection_after.clone())
+    }
 
-        let cursor_pos = match &edit {
-            Edit::Insert { pos, text } => {
-                // Redo an insert
-                self.rope.insert(*pos, text);
-                pos + text.len()
+    /// Move to any state in the undo tree, returning the selection from that state
+    pub fn jump_to(&mut self, id: TransactionId) -> Option<Selection> {
+        let (revert, apply) = self.history.jump_to(id)?;
+        for transaction in &revert {
+            revert_transaction(&mut self.rope, transaction);
+        }
+        for transaction in &apply {
+            apply_transaction(&mut self.rope, transaction);
+        }
+        let selection = match (apply.last(), revert.last()) {
+            (Some(transaction), _) => transaction.selection_after.clone(),
+            (None, Some(transaction)) => transaction.selection_before.clone(),
+            (None, None) => return None,
+        };
+        self.version += 1;
+        Some(selection)
+    }
+
+    /// Get the text as it was in a state of the undo tree, leaving the buffer as it is
+    pub fn text_at(&self, id: TransactionId) -> Rope {
+        let mut rope = self.rope.clone();
+        let (revert, apply) = self.history.path_to(id);
+        for id in revert {
+            if let Some(transaction) = self.history.transaction(id) {
+                revert_transaction(&mut rope, transaction);
             }
-            Edit::Remove { pos, text } => {
-                // Redo a remove
-                let end = pos + text.len();
-                self.rope.remove(*pos..end);
-                *pos
+        }
+        for id in apply {
+            if let Some(transaction) = self.history.transaction(id) {
+                apply_transaction(&mut rope, transaction);
             }
-        };
+        }
+        rope
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// Replace the history, which must end in a state matching the current text
+    pub fn set_history(&mut self, history: History) {
+        self.history = history;
+        self.history.mark_saved();
+        self.version += 1;
+    }
 
-        self.undo_stack.push(edit);
-        Some(cursor_pos)
+    pub fn version(&self) -> usize {
+        self.version
     }
 
     /// Check if undo is available
     pub fn can_undo(&self) -> bool {
-        !self.undo_stack.is_empty()
+        self.history.can_undo()
     }
 
     /// Check if redo is available
     pub fn can_redo(&self) -> bool {
-        !self.redo_stack.is_empty()
+        self.history.can_redo()
+    }
+}
+
+/// Byte offset of each token in the text it was split from, plus the text's length
+fn token_offsets(tokens: &[&str]) -> Vec<usize> {
+    let mut offsets = Vec::with_capacity(tokens.len() + 1);
+    let mut offset = 0;
+    offsets.push(offset);
+    for token in tokens {
+        offset += token.len();
+        offsets.push(offset);
+    }
+    offsets
+}
+
+/// Carry an offset across replaced ranges, given in order as old range and new text. An
+/// offset inside a replaced range keeps its distance from the start where it can.
+fn map_offset(offset: usize, hunks: &[(Range<usize>, &str)]) -> usize {
+    let mut mapped = offset;
+    for (old, new) in hunks {
+        if offset < old.start {
+            break;
+        }
+        if offset < old.end {
+            let start = mapped - (offset - old.start);
+            return start + (offset - old.start).min(new.len());
+        }
+        mapped = mapped + new.len() - old.len();
+    }
+    mapped
+}
+
+fn remove_bytes(rope: &mut Rope, range: Range<ByteOffset>) {
+    let start = rope.byte_to_char(range.start.0);
+    let end = rope.byte_to_char(range.end.0);
+    rope.remove(start..end);
+}
+
+fn apply_transaction(rope: &mut Rope, transaction: &Transaction) {
+    for edit in &transaction.edits {
+        match edit {
+            Edit::Insert { pos, text } => rope.insert(rope.byte_to_char(pos.0), text),
+            Edit::Remove { pos, text } => remove_bytes(rope, *pos..*pos + text.len()),
+        }
+    }
+}
+
+fn revert_transaction(rope: &mut Rope, transaction: &Transaction) {
+    for edit in transaction.edits.iter().rev() {
+        match edit {
+            Edit::Insert { pos, text } => remove_bytes(rope, *pos..*pos + text.len()),
+            Edit::Remove { pos, text } => rope.insert(rope.byte_to_char(pos.0), text),
+        }
+    }
+}
+
+/// Length in bytes of the line break ending a line, if any. Ropey splits lines on
+/// CRLF and the Unicode line separators as well as plain LF.
+fn line_break_len(line: RopeSlice) -> usize {
+    let len = line.len_chars();
+    match (
+        len.checked_sub(2).map(|i| line.char(i)),
+        len.checked_sub(1).map(|i| line.char(i)),
+    ) {
+        (Some('\r'), Some('\n')) => 2,
+        (
+            _,
+            Some(
+                c @ ('\n' | '\r' | '\u{000B}' | '\u{000C}' | '\u{0085}' | '\u{2028}' | '\u{2029}'),
+            ),
+        ) => c.len_utf8(),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::test_support::TempDir;
+
+    #[test]
+    fn test_offset_and_char_conversions() {
+        // "é" is 2 bytes and "😀" is 4 bytes, each one char
+        let buffer = Buffer::with_text("aé😀b");
+        assert_eq!(buffer.len_bytes(), ByteOffset(8));
+        assert_eq!(buffer.len_chars(), CharOffset(4));
+        assert_eq!(buffer.byte_to_char(ByteOffset(3)), CharOffset(2));
+        assert_eq!(buffer.char_to_byte(CharOffset(3)), ByteOffset(7));
+        // Offsets inside a char round down to its start
+        assert_eq!(buffer.clip_offset(ByteOffset(2)), ByteOffset(1));
+        assert_eq!(buffer.clip_offset(ByteOffset(5)), ByteOffset(3));
+        // Past the end clamps to the end
+        assert_eq!(buffer.byte_to_char(ByteOffset(100)), CharOffset(4));
+        assert_eq!(buffer.char_to_byte(CharOffset(100)), ByteOffset(8));
+    }
+
+    #[test]
+    fn test_utf16_offsets() {
+        // "😀" is a surrogate pair, two UTF-16 code units
+        let buffer = Buffer::with_text("aé😀b");
+        assert_eq!(buffer.offset_to_offset_utf16(ByteOffset(3)), OffsetUtf16(2));
+        assert_eq!(buffer.offset_to_offset_utf16(ByteOffset(7)), OffsetUtf16(4));
+        assert_eq!(buffer.offset_to_offset_utf16(ByteOffset(8)), OffsetUtf16(5));
+        assert_eq!(buffer.offset_utf16_to_offset(OffsetUtf16(4)), ByteOffset(7));
+        // Halfway through a surrogate pair rounds down to the start of the char
+        assert_eq!(buffer.offset_utf16_to_offset(OffsetUtf16(3)), ByteOffset(3));
+        assert_eq!(
+            buffer.offset_utf16_to_offset(OffsetUtf16(100)),
+            ByteOffset(8)
+        );
+    }
+
+    #[test]
+    fn test_points() {
+        let buffer = Buffer::with_text("héllo\nwörld\n");
+        assert_eq!(buffer.len_lines(), 3);
+        assert_eq!(buffer.line_len(0), 6);
+        assert_eq!(buffer.offset_to_point(ByteOffset(7)), Point::new(1, 0));
+        assert_eq!(buffer.offset_to_point(ByteOffset(10)), Point::new(1, 3));
+        assert_eq!(buffer.point_to_offset(Point::new(1, 3)), ByteOffset(10));
+        // Columns past the end of a row clamp to it, and rows past the end to the buffer's end
+        assert_eq!(buffer.point_to_offset(Point::new(0, 100)), ByteOffset(6));
+        assert_eq!(buffer.point_to_offset(Point::new(9, 0)), buffer.len_bytes());
+        // A column inside a char rounds down
+        assert_eq!(buffer.point_to_offset(Point::new(0, 2)), ByteOffset(1));
+        // The end of the buffer is the start of the empty last line
+        assert_eq!(buffer.max_point(), Point::new(2, 0));
+    }
+
+    #[test]
+    fn test_points_with_crlf() {
+        // CRLF is normalized on the way in, but text inserted as-is can still hold it
+        let mut buffer = Buffer::with_text("ab\r\ncd");
+        assert_eq!(buffer.to_string(), "ab\ncd");
+        assert_eq!(buffer.offset_to_point(ByteOffset(3)), Point::new(1, 0));
+
+        buffer.insert(ByteOffset(0), "x\r\n");
+        assert_eq!(buffer.line_len(0), 1);
+        assert_eq!(buffer.line(0).to_string(), "x");
+        assert_eq!(buffer.offset_to_point(ByteOffset(3)), Point::new(1, 0));
+        assert_eq!(buffer.point_to_offset(Point::new(0, 5)), ByteOffset(1));
+    }
+
+    #[test]
+    fn test_utf16_points() {
+        let buffer = Buffer::with_text("a😀b\né");
+        assert_eq!(
+            buffer.point_to_point_utf16(Point::new(0, 5)),
+            PointUtf16::new(0, 3)
+        );
+        assert_eq!(
+            buffer.point_utf16_to_point(PointUtf16::new(0, 3)),
+            Point::new(0, 5)
+        );
+        // Inside the surrogate pair rounds down
+        assert_eq!(
+            buffer.point_utf16_to_point(PointUtf16::new(0, 2)),
+            Point::new(0, 1)
+        );
+        // Past the end of a row clamps to it, not into the next row
+        assert_eq!(
+            buffer.point_utf16_to_point(PointUtf16::new(0, 100)),
+            Point::new(0, 6)
+        );
+        assert_eq!(
+            buffer.point_to_point_utf16(Point::new(1, 2)),
+            PointUtf16::new(1, 1)
+        );
+        assert_eq!(
+            buffer.point_utf16_to_point(PointUtf16::new(5, 0)),
+            buffer.max_point()
+        );
+    }
+
+    #[test]
+    fn test_reopen_with_encoding() {
+        let dir = TempDir::new("reopen");
+        let path = dir.join("notes.txt");
+        std::fs::write(&path, "café\n").unwrap();
+
+        let mut buffer = Buffer::from_file(path).unwrap();
+        assert_eq!(buffer.encoding().encoding, encoding_rs::UTF_8);
+        buffer
+            .reopen_with_encoding(encoding_rs::WINDOWS_1252)
+            .unwrap();
+        assert_eq!(buffer.to_string(), "cafÃ©\n");
+        assert_eq!(buffer.encoding().encoding, encoding_rs::WINDOWS_1252);
+        assert!(!buffer.is_dirty());
+        buffer.reopen_with_encoding(encoding_rs::UTF_8).unwrap();
+        assert_eq!(buffer.to_string(), "café\n");
+    }
+
+    #[test]
+    fn test_save_with_encoding_round_trip() {
+        let dir = TempDir::new("save-encoding");
+        let path = dir.join("notes.txt");
+        std::fs::write(&path, "").unwrap();
+        let text = "héllo\nwörld\n";
+
+        for encoding in [encoding_rs::UTF_16LE, encoding_rs::UTF_16BE] {
+            for has_bom in [true, false] {
+                let mut buffer = Buffer::from_file(path.clone()).unwrap();
+                buffer.set_text(text);
+                let encoding = TextEncoding { encoding, has_bom };
+                buffer.save_with_encoding(encoding).unwrap();
+                assert_eq!(buffer.encoding(), encoding);
+                assert!(!buffer.is_dirty());
+
+                let bytes = std::fs::read(&path).unwrap();
+                let bom: &[u8] = if encoding.encoding == encoding_rs::UTF_16LE {
+                    b"\xFF\xFE"
+                } else {
+                    b"\xFE\xFF"
+                };
+                assert_eq!(bytes.starts_with(bom), has_bom);
+
+                // Opening the file again finds the same encoding and text
+                let reopened = Buffer::from_file(path.clone()).unwrap();
+                assert_eq!(reopened.to_string(), text);
+                assert_eq!(reopened.encoding(), encoding);
+            }
+        }
+    }
+
+    #[test]
+    fn test_save_unencodable() {
+        let dir = TempDir::new("save-unencodable");
+        let path = dir.join("notes.txt");
+        std::fs::write(&path, "plain\n").unwrap();
+
+        let mut buffer = Buffer::from_file(path.clone()).unwrap();
+        buffer.set_text("日本語\n");
+        let result = buffer.save_with_encoding(TextEncoding::new(encoding_rs::WINDOWS_1252));
+        assert!(matches!(
+            result,
+            Err(SaveError::Unencodable {
+                encoding: "windows-1252"
+            })
+        ));
+        // Nothing is written and the buffer keeps its encoding and changes
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "plain\n");
+        assert_eq!(buffer.encoding().encoding, encoding_rs::UTF_8);
+        assert!(buffer.is_dirty());
     }
 }
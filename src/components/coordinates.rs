@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+use std::ops::{Add, AddAssign, Sub, SubAssign};
+
+/// An offset into a buffer measured in UTF-8 bytes
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct ByteOffset(pub usize);
+
+/// An offset into a buffer measured in chars (Unicode scalar values), the unit ropey indexes by
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CharOffset(pub usize);
+
+/// An offset into a buffer measured in UTF-16 code units, the unit platform text input uses
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OffsetUtf16(pub usize);
+
+/// A zero-based row and a column measured in UTF-8 bytes from the start of that row
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Point {
+    pub row: u32,
+    pub column: u32,
+}
+
+/// A zero-based row and a column measured in UTF-16 code units, as used by LSP
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PointUtf16 {
+    pub row: u32,
+    pub column: u32,
+}
+
+macro_rules! impl_offset_ops {
+    ($offset:ident) => {
+        impl Add<usize> for $offset {
+            type Output = Self;
+
+            fn add(self, rhs: usize) -> Self {
+                Self(self.0 + rhs)
+            }
+        }
+
+        impl AddAssign<usize> for $offset {
+            fn add_assign(&mut self, rhs: usize) {
+                self.0 += rhs;
+            }
+        }
+
+        impl Sub<usize> for $offset {
+            type Output = Self;
+
+            fn sub(self, rhs: usize) -> Self {
+                Self(self.0 - rhs)
+            }
+        }
+
+        impl SubAssign<usize> for $offset {
+            fn sub_assign(&mut self, rhs: usize) {
+                self.0 -= rhs;
+            }
+        }
+
+        /// The distance between two offsets
+        impl Sub for $offset {
+            type Output = usize;
+
+            fn sub(self, rhs: Self) -> usize {
+                self.0 - rhs.0
+            }
+        }
+    };
+}
+
+impl_offset_ops!(ByteOffset);
+impl_offset_ops!(CharOffset);
+impl_offset_ops!(OffsetUtf16);
+
+impl Point {
+    pub fn new(row: u32, column: u32) -> Self {
+        Self { row, column }
+    }
+}
+
+impl PointUtf16 {
+    pub fn new(row: u32, column: u32) -> Self {
+        Self { row, column }
+    }
+}
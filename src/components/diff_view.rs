@@ -0,0 +1,65 @@
+use gpui::{prelude::*, *};
+use similar::{ChangeTag, TextDiff};
+
+/// Unchanged lines shown around each change
+const DIFF_CONTEXT_LINES: usize = 2;
+
+/// The lines that turning one text into another removes and adds, with a little context,
+/// worked out once so it can be shown on every render
+pub struct LineDiff {
+    /// Runs of nearby changes, each line with whether it's removed, added or unchanged
+    hunks: Vec<Vec<(ChangeTag, String)>>,
+}
+
+impl LineDiff {
+    pub fn new(old: &str, new: &str) -> Self {
+        let diff = TextDiff::from_lines(old, new);
+        let hunks = diff
+            .grouped_ops(DIFF_CONTEXT_LINES)
+            .iter()
+            .map(|group| {
+                group
+                    .iter()
+                    .flat_map(|op| diff.iter_changes(op))
+                    .map(|change| {
+                        let text = change.value().trim_end_matches(['\n', '\r']);
+                        (change.tag(), text.to_string())
+                    })
+                    .collect()
+            })
+            .collect();
+        Self { hunks }
+    }
+
+    pub fn render(&self) -> Div {
+        let mut lines = div().flex().flex_col().font_family("monospace").text_xs();
+        if self.hunks.is_empty() {
+            lines = lines.child(
+                div()
+                    .p_2()
+                    .text_color(rgb(0x999999))
+                    .child("Same as the current text"),
+            );
+        }
+        for (ix, hunk) in self.hunks.iter().enumerate() {
+            if ix > 0 {
+                lines = lines.child(div().px_2().text_color(rgb(0x999999)).child("…"));
+            }
+            for (tag, text) in hunk {
+                let (sign, background, color) = match tag {
+                    ChangeTag::Delete => ("-", rgb(0xffebe9), rgb(0x82071e)),
+                    ChangeTag::Insert => ("+", rgb(0xe6ffec), rgb(0x116329)),
+                    ChangeTag::Equal => (" ", rgb(0xffffff), rgb(0x666666)),
+                };
+                lines = lines.child(
+                    div()
+                        .px_2()
+                        .bg(background)
+                        .text_color(color)
+                        .child(format!("{} {}", sign, text)),
+                );
+            }
+        }
+        lines
+    }
+}
@@ -0,0 +1,48 @@
+use sha2::{Digest, Sha256};
+use std::fs::Metadata;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// What a file on disk looked like when a buffer was loaded from or saved to it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiskFingerprint {
+    mtime: Option<SystemTime>,
+    len: u64,
+    /// Hash of the file's bytes, for when the mtime changes but the content doesn't
+    content_hash: String,
+}
+
+impl DiskFingerprint {
+    pub fn new(bytes: &[u8], metadata: &Metadata) -> Self {
+        Self {
+            mtime: metadata.modified().ok(),
+            len: metadata.len(),
+            content_hash: format!("{:x}", Sha256::digest(bytes)),
+        }
+    }
+
+    /// Read a file along with its fingerprint
+    pub fn read(path: &Path) -> std::io::Result<(Vec<u8>, Self)> {
+        let metadata = std::fs::metadata(path)?;
+        let bytes = std::fs::read(path)?;
+        let fingerprint = Self::new(&bytes, &metadata);
+        Ok((bytes, fingerprint))
+    }
+
+    /// Whether the file has certainly not been touched, without reading it
+    pub fn matches_metadata(&self, metadata: &Metadata) -> bool {
+        self.mtime.is_some() && self.mtime == metadata.modified().ok() && self.len == metadata.len()
+    }
+
+    pub fn matches_content(&self, other: &Self) -> bool {
+        self.content_hash == other.content_hash
+    }
+}
+
+/// How the file behind a buffer compares to the version the buffer knows about
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiskChange {
+    Unchanged,
+    Modified,
+    Deleted,
+}
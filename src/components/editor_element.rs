@@ -0,0 +1,502 @@
+use crate::components::{LineNumbers, Point, TextEditor};
+use gpui::*;
+use std::ops::Range;
+
+/// Rows laid out past each edge of the viewport so short scrolls don't show blank space
+const OVERSCAN_ROWS: u32 = 3;
+pub const SCROLLBAR_WIDTH: Pixels = px(8.);
+const SCROLLBAR_MIN_THUMB_HEIGHT: Pixels = px(20.);
+/// Space on each side of the line numbers
+const GUTTER_PADDING: Pixels = px(12.);
+
+/// The shaped rows from the editor's last paint, used to map between window positions and
+/// buffer points
+pub struct EditorLayout {
+    /// Bounds of the text area
+    pub bounds: Bounds<Pixels>,
+    /// Bounds of the line number column left of the text area
+    pub gutter_bounds: Bounds<Pixels>,
+    pub line_height: Pixels,
+    /// How far the content was scrolled when it was laid out
+    pub scroll_position: gpui::Point<Pixels>,
+    /// The largest scroll position on each axis
+    pub scroll_max: gpui::Point<Pixels>,
+    /// The buffer row of the first shaped line
+    pub first_row: u32,
+    /// One shaped line per visible row, plus overscan
+    pub lines: Vec<ShapedLine>,
+    /// Present when the content is taller than the viewport
+    pub scrollbar: Option<ScrollbarLayout>,
+}
+
+impl EditorLayout {
+    fn line(&self, row: u32) -> Option<&ShapedLine> {
+        self.lines.get(row.checked_sub(self.first_row)? as usize)
+    }
+
+    /// Horizontal position of a point relative to the unscrolled text origin
+    pub fn x_for_point(&self, point: Point) -> Pixels {
+        self.line(point.row)
+            .map_or(px(0.), |line| line.x_for_index(point.column as usize))
+    }
+
+    /// Map a window position to the nearest point, clamping to the laid out rows
+    pub fn point_for_position(&self, position: gpui::Point<Pixels>) -> Point {
+        let last_row = self.first_row + self.lines.len().saturating_sub(1) as u32;
+        let row = ((position.y - self.bounds.top() + self.scroll_position.y) / self.line_height)
+            .floor()
+            .max(0.) as u32;
+        let row = row.clamp(self.first_row, last_row);
+        let column = self.line(row).map_or(0, |line| {
+            line.closest_index_for_x(position.x - self.bounds.left() + self.scroll_position.x)
+        });
+        Point::new(row, column as u32)
+    }
+}
+
+/// Where the vertical scrollbar was painted
+#[derive(Clone, Copy)]
+pub struct ScrollbarLayout {
+    pub track: Bounds<Pixels>,
+    pub thumb: Bounds<Pixels>,
+}
+
+impl ScrollbarLayout {
+    /// Place the thumb in `track` for content `content_height` tall, scrolled down by
+    /// `scroll_y` out of at most `max_y`
+    pub fn new(
+        track: Bounds<Pixels>,
+        content_height: Pixels,
+        scroll_y: Pixels,
+        max_y: Pixels,
+    ) -> Self {
+        let thumb_height = (track.size.height * (track.size.height / content_height))
+            .max(SCROLLBAR_MIN_THUMB_HEIGHT)
+            .min(track.size.height);
+        let thumb_top = track.top() + (track.size.height - thumb_height) * (scroll_y / max_y);
+        Self {
+            track,
+            thumb: Bounds::new(
+                point(track.left(), thumb_top),
+                size(track.size.width, thumb_height),
+            ),
+        }
+    }
+
+    /// The vertical scroll position that puts the top of the thumb at `thumb_top`
+    pub fn scroll_y_for_thumb_top(&self, thumb_top: Pixels, scroll_max: Pixels) -> Pixels {
+        let travel = self.track.size.height - self.thumb.size.height;
+        if travel <= px(0.) {
+            return px(0.);
+        }
+        let fraction = ((thumb_top - self.track.top()) / travel).clamp(0., 1.);
+        scroll_max * fraction
+    }
+}
+
+/// Paints a TextEditor's rows, selection and cursor, and receives its text input
+pub struct EditorElement {
+    editor: Entity<TextEditor>,
+}
+
+impl EditorElement {
+    pub fn new(editor: Entity<TextEditor>) -> Self {
+        Self { editor }
+    }
+}
+
+pub struct EditorPrepaintState {
+    gutter_bounds: Bounds<Pixels>,
+    text_bounds: Bounds<Pixels>,
+    line_height: Pixels,
+    scroll_position: gpui::Point<Pixels>,
+    scroll_max: gpui::Point<Pixels>,
+    first_row: u32,
+    lines: Vec<ShapedLine>,
+    line_numbers: Vec<ShapedLine>,
+    current_line: Option<PaintQuad>,
+    selections: Vec<PaintQuad>,
+    cursor: Option<PaintQuad>,
+    scrollbar: Option<ScrollbarLayout>,
+}
+
+impl IntoElement for EditorElement {
+    type Element = Self;
+
+    fn into_element(self) -> Self::Element {
+        self
+    }
+}
+
+impl Element for EditorElement {
+    type RequestLayoutState = ();
+    type PrepaintState = EditorPrepaintState;
+
+    fn id(&self) -> Option<ElementId> {
+        None
+    }
+
+    fn source_location(&self) -> Option<&'static core::panic::Location<'static>> {
+        None
+    }
+
+    fn request_layout(
+        &mut self,
+        _id: Option<&GlobalElementId>,
+        _inspector_id: Option<&InspectorElementId>,
+        window: &mut Window,
+        cx: &mut App,
+    ) -> (LayoutId, Self::RequestLayoutState) {
+        let mut style = Style::default();
+        style.size.width = relative(1.).into();
+        style.size.height = relative(1.).into();
+        (window.request_layout(style, [], cx), ())
+    }
+
+    fn prepaint(
+        &mut self,
+        _id: Option<&GlobalElementId>,
+        _inspector_id: Option<&InspectorElementId>,
+        bounds: Bounds<Pixels>,
+        _request_layout: &mut Self::RequestLayoutState,
+        window: &mut Window,
+        cx: &mut App,
+    ) -> Self::PrepaintState {
+        let style = window.text_style();
+        let font_size = style.font_size.to_pixels(window.rem_size());
+        let line_height = window.line_height();
+
+        let (mut scroll_position, autoscroll) = self.editor.update(cx, |editor, _cx| {
+            (editor.scroll_position(), editor.take_autoscroll_request())
+        });
+        let editor = self.editor.read(cx);
+        let buffer = editor.buffer();
+        let selection = editor.selection();
+        let head = buffer.offset_to_point(selection.head);
+        let row_count = buffer.len_lines();
+        let line_number_mode = editor.line_number_mode();
+
+        // The gutter is as wide as the largest line number
+        let gutter_width = if line_number_mode == LineNumbers::Off {
+            px(0.)
+        } else {
+            let digits = row_count.max(1).ilog10() as usize + 1;
+            let digits_width = window
+                .text_system()
+                .shape_line(
+                    "0".repeat(digits).into(),
+                    font_size,
+                    &[style.to_run(digits)],
+                    None,
+                )
+                .width;
+            digits_width + GUTTER_PADDING * 2.
+        };
+        let gutter_bounds = Bounds::new(bounds.origin, size(gutter_width, bounds.size.height));
+        let text_bounds = Bounds::from_corners(
+            point(gutter_bounds.right(), bounds.top()),
+            point(
+                (bounds.right() - SCROLLBAR_WIDTH).max(gutter_bounds.right()),
+                bounds.bottom(),
+            ),
+        );
+
+        // Keep the cursor's row inside the viewport
+        let content_height = line_height * row_count as f32;
+        let max_y = (content_height - text_bounds.size.height).max(px(0.));
+        if autoscroll {
+            let top = line_height * head.row as f32;
+            if top < scroll_position.y {
+                scroll_position.y = top;
+            } else if top + line_height > scroll_position.y + text_bounds.size.height {
+                scroll_position.y = top + line_height - text_bounds.size.height;
+            }
+        }
+        scroll_position.y = scroll_position.y.clamp(px(0.), max_y);
+
+        // Only the visible rows and a little overscan are shaped
+        let first_row =
+            ((scroll_position.y / line_height).floor() as u32).saturating_sub(OVERSCAN_ROWS);
+        let end_row = (((scroll_position.y + text_bounds.size.height) / line_height).ceil() as u32
+            + OVERSCAN_ROWS)
+            .min(row_count);
+
+        let point_range = |range: Range<_>| {
+            buffer.offset_to_point(range.start)..buffer.offset_to_point(range.end)
+        };
+        let selected = (!selection.is_empty()).then(|| point_range(selection.range()));
+        let marked = editor.marked_range().map(point_range);
+
+        // Shape the rows, underlining text an IME is composing
+        let lines = (first_row..end_row)
+            .map(|row| {
+                let text: SharedString = buffer.line(row).to_string().into();
+                let run = style.to_run(text.len());
+                let runs = match marked
+                    .as_ref()
+                    .and_then(|range| clip_to_row(range, row, &text))
+                {
+                    Some(marked) => vec![
+                        TextRun {
+                            len: marked.start,
+                            ..run.clone()
+                        },
+                        TextRun {
+                            len: marked.len(),
+                            underline: Some(UnderlineStyle {
+                                color: Some(run.color),
+                                thickness: px(1.),
+                                wavy: false,
+                            }),
+                            ..run.clone()
+                        },
+                        TextRun {
+                            len: text.len() - marked.end,
+                            ..run
+                        },
+                    ],
+                    None => vec![run],
+                };
+                let runs = runs
+                    .into_iter()
+                    .filter(|run| run.len > 0)
+                    .collect::<Vec<_>>();
+                window
+                    .text_system()
+                    .shape_line(text, font_size, &runs, None)
+            })
+            .collect::<Vec<_>>();
+        let line = |row: u32| lines.get(row.checked_sub(first_row)? as usize);
+
+        let line_numbers = (first_row..end_row)
+            .filter_map(|row| {
+                let number = match line_number_mode {
+                    LineNumbers::Absolute => row + 1,
+                    LineNumbers::Relative => row.abs_diff(head.row),
+                    LineNumbers::Hybrid if row == head.row => row + 1,
+                    LineNumbers::Hybrid => row.abs_diff(head.row),
+                    LineNumbers::Off => return None,
+                };
+                let text: SharedString = number.to_string().into();
+                let color = if row == head.row {
+                    rgb(0x333333)
+                } else {
+                    rgb(0x999999)
+                };
+                let run = TextRun {
+                    color: color.into(),
+                    ..style.to_run(text.len())
+                };
+                Some(
+                    window
+                        .text_system()
+                        .shape_line(text, font_size, &[run], None),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // Horizontal extent is measured from the shaped rows only
+        let cursor_width = px(2.);
+        let widest = lines
+            .iter()
+            .map(|line| line.width)
+            .max()
+            .unwrap_or_default();
+        let max_x = (widest + cursor_width - text_bounds.size.width).max(px(0.));
+        if autoscroll {
+            if let Some(line) = line(head.row) {
+                let x = line.x_for_index(head.column as usize);
+                if x < scroll_position.x {
+                    scroll_position.x = x;
+                } else if x + cursor_width > scroll_position.x + text_bounds.size.width {
+                    scroll_position.x = x + cursor_width - text_bounds.size.width;
+                }
+            }
+        }
+        scroll_position.x = scroll_position.x.clamp(px(0.), max_x);
+
+        let row_top = |row: u32| text_bounds.top() + line_height * row as f32 - scroll_position.y;
+        let left = text_bounds.left() - scroll_position.x;
+
+        let current_line = (first_row..end_row).contains(&head.row).then(|| {
+            fill(
+                Bounds::from_corners(
+                    point(gutter_bounds.left(), row_top(head.row)),
+                    point(text_bounds.right(), row_top(head.row + 1)),
+                ),
+                rgb(0xf5f7fa),
+            )
+        });
+
+        // Selected rows continue past their end to show that the line break is selected
+        let selections = selected
+            .map(|range| {
+                (range.start.row.max(first_row)..=range.end.row.min(end_row.saturating_sub(1)))
+                    .filter_map(|row| {
+                        let line = line(row)?;
+                        let columns = clip_to_row(&range, row, &line.text).unwrap_or(0..0);
+                        let trailing = if row < range.end.row {
+                            font_size / 2.
+                        } else {
+                            px(0.)
+                        };
+                        Some(fill(
+                            Bounds::from_corners(
+                                point(left + line.x_for_index(columns.start), row_top(row)),
+                                point(
+                                    left + line.x_for_index(columns.end) + trailing,
+                                    row_top(row + 1),
+                                ),
+                            ),
+                            rgb(0xb3d7ff),
+                        ))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let cursor = line(head.row).map(|line| {
+            fill(
+                Bounds::new(
+                    point(
+                        left + line.x_for_index(head.column as usize),
+                        row_top(head.row),
+                    ),
+                    size(cursor_width, line_height),
+                ),
+                rgb(0x0066ff),
+            )
+        });
+
+        let scrollbar = (max_y > px(0.)).then(|| {
+            let track = Bounds::new(
+                point(text_bounds.right(), bounds.top()),
+                size(SCROLLBAR_WIDTH, bounds.size.height),
+            );
+            ScrollbarLayout::new(track, content_height, scroll_position.y, max_y)
+        });
+
+        self.editor.update(cx, |editor, _cx| {
+            editor.set_scroll_position(scroll_position)
+        });
+
+        EditorPrepaintState {
+            gutter_bounds,
+            text_bounds,
+            line_height,
+            scroll_position,
+            scroll_max: point(max_x, max_y),
+            first_row,
+            lines,
+            line_numbers,
+            current_line,
+            selections,
+            cursor,
+            scrollbar,
+        }
+    }
+
+    fn paint(
+        &mut self,
+        _id: Option<&GlobalElementId>,
+        _inspector_id: Option<&InspectorElementId>,
+        bounds: Bounds<Pixels>,
+        _request_layout: &mut Self::RequestLayoutState,
+        prepaint: &mut Self::PrepaintState,
+        window: &mut Window,
+        cx: &mut App,
+    ) {
+        let focus_handle = self.editor.read(cx).focus_handle(cx);
+        window.handle_input(
+            &focus_handle,
+            ElementInputHandler::new(bounds, self.editor.clone()),
+            cx,
+        );
+
+        let gutter_bounds = prepaint.gutter_bounds;
+        let text_bounds = prepaint.text_bounds;
+        let line_height = prepaint.line_height;
+        let scroll_position = prepaint.scroll_position;
+        let row_top = |row: u32| text_bounds.top() + line_height * row as f32 - scroll_position.y;
+
+        window.with_content_mask(
+            Some(ContentMask {
+                bounds: gutter_bounds.union(&text_bounds),
+            }),
+            |window| {
+                if let Some(current_line) = prepaint.current_line.take() {
+                    window.paint_quad(current_line);
+                }
+
+                // Line numbers are right aligned against the text area
+                for (ix, number) in prepaint.line_numbers.iter().enumerate() {
+                    let row = prepaint.first_row + ix as u32;
+                    let origin = point(
+                        gutter_bounds.right() - GUTTER_PADDING - number.width,
+                        row_top(row),
+                    );
+                    number.paint(origin, line_height, window, cx).ok();
+                }
+            },
+        );
+
+        window.with_content_mask(
+            Some(ContentMask {
+                bounds: text_bounds,
+            }),
+            |window| {
+                for selection in prepaint.selections.drain(..) {
+                    window.paint_quad(selection);
+                }
+
+                for (ix, line) in prepaint.lines.iter().enumerate() {
+                    let row = prepaint.first_row + ix as u32;
+                    let origin = point(text_bounds.left() - scroll_position.x, row_top(row));
+                    line.paint(origin, line_height, window, cx).ok();
+                }
+
+                if focus_handle.is_focused(window) {
+                    if let Some(cursor) = prepaint.cursor.take() {
+                        window.paint_quad(cursor);
+                    }
+                }
+            },
+        );
+
+        if let Some(scrollbar) = prepaint.scrollbar {
+            window.paint_quad(fill(scrollbar.track, rgb(0xf0f0f0)));
+            window.paint_quad(fill(scrollbar.thumb, rgb(0xc1c1c1)).corner_radii(px(4.)));
+        }
+
+        let layout = EditorLayout {
+            bounds: text_bounds,
+            gutter_bounds,
+            line_height,
+            scroll_position,
+            scroll_max: prepaint.scroll_max,
+            first_row: prepaint.first_row,
+            lines: std::mem::take(&mut prepaint.lines),
+            scrollbar: prepaint.scrollbar,
+        };
+        self.editor
+            .update(cx, |editor, _cx| editor.set_layout(layout));
+    }
+}
+
+/// Get the byte columns of a row covered by a range of points
+fn clip_to_row(range: &Range<Point>, row: u32, line: &str) -> Option<Range<usize>> {
+    if row < range.start.row || row > range.end.row {
+        return None;
+    }
+    let start = if row == range.start.row {
+        range.start.column as usize
+    } else {
+        0
+    };
+    let end = if row == range.end.row {
+        range.end.column as usize
+    } else {
+        line.len()
+    };
+    Some(start..end)
+}
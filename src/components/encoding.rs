@@ -0,0 +1,247 @@
+use crate::components::save::SaveError;
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+
+/// Encodings offered when reopening or saving with a specific encoding
+pub const ENCODINGS: &[&Encoding] = &[
+    UTF_8,
+    UTF_16LE,
+    UTF_16BE,
+    encoding_rs::WINDOWS_1252,
+    encoding_rs::ISO_8859_2,
+    encoding_rs::ISO_8859_15,
+    encoding_rs::WINDOWS_1251,
+    encoding_rs::KOI8_R,
+    encoding_rs::SHIFT_JIS,
+    encoding_rs::EUC_JP,
+    encoding_rs::EUC_KR,
+    encoding_rs::GBK,
+    encoding_rs::GB18030,
+    encoding_rs::BIG5,
+];
+
+/// How a file's bytes map to text, and whether it starts with a byte order mark
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextEncoding {
+    pub encoding: &'static Encoding,
+    pub has_bom: bool,
+}
+
+impl Default for TextEncoding {
+    fn default() -> Self {
+        Self::new(UTF_8)
+    }
+}
+
+impl TextEncoding {
+    /// An encoding without a byte order mark, except UTF-16 which is unreadable without one
+    pub fn new(encoding: &'static Encoding) -> Self {
+        Self {
+            encoding,
+            has_bom: encoding == UTF_16LE || encoding == UTF_16BE,
+        }
+    }
+
+    /// A chosen encoding for a file's bytes, keeping the byte order mark it has
+    pub fn for_file(encoding: &'static Encoding, bytes: &[u8]) -> Self {
+        let bom = bom_for(encoding);
+        Self {
+            encoding,
+            has_bom: !bom.is_empty() && bytes.starts_with(bom),
+        }
+    }
+
+    /// Work out a file's encoding and decode it. A byte order mark decides, then the NUL
+    /// pattern of BOM-less UTF-16, then valid UTF-8, then a guess between legacy encodings.
+    pub fn detect(bytes: &[u8]) -> (String, Self) {
+        if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+            let encoding = Self {
+                encoding,
+                has_bom: true,
+            };
+            return (encoding.decode(bytes), encoding);
+        }
+
+        // NULs are valid UTF-8, so UTF-16 is checked for first
+        let encoding = if let Some(encoding) = detect_utf16(bytes) {
+            encoding
+        } else if std::str::from_utf8(bytes).is_ok() {
+            UTF_8
+        } else {
+            let mut detector = EncodingDetector::new();
+            detector.feed(bytes, true);
+            detector.guess(None, true)
+        };
+        let encoding = Self {
+            encoding,
+            has_bom: false,
+        };
+        (encoding.decode(bytes), encoding)
+    }
+
+    /// Decode bytes, skipping a byte order mark for this encoding if present
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        let bytes = bytes.strip_prefix(bom_for(self.encoding)).unwrap_or(bytes);
+        let (text, _had_errors) = self.encoding.decode_without_bom_handling(bytes);
+        text.into_owned()
+    }
+
+    /// Encode text, failing if it has characters the encoding can't represent
+    pub fn encode(&self, text: &str) -> Result<Vec<u8>, SaveError> {
+        let mut bytes = if self.has_bom {
+            bom_for(self.encoding).to_vec()
+        } else {
+            Vec::new()
+        };
+        // encoding_rs only decodes UTF-16, so it's encoded here
+        if self.encoding == UTF_16LE {
+            bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+        } else if self.encoding == UTF_16BE {
+            bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+        } else {
+            let (encoded, _, had_errors) = self.encoding.encode(text);
+            if had_errors {
+                return Err(SaveError::Unencodable {
+                    encoding: self.encoding.name(),
+                });
+            }
+            bytes.extend_from_slice(&encoded);
+        }
+        Ok(bytes)
+    }
+
+    /// Name for the status area
+    pub fn label(&self) -> String {
+        if self.has_bom && self.encoding == UTF_8 {
+            "UTF-8 with BOM".to_string()
+        } else {
+            self.encoding.name().to_string()
+        }
+    }
+}
+
+/// The byte order mark for an encoding, empty for encodings that don't have one
+fn bom_for(encoding: &'static Encoding) -> &'static [u8] {
+    if encoding == UTF_8 {
+        b"\xEF\xBB\xBF"
+    } else if encoding == UTF_16LE {
+        b"\xFF\xFE"
+    } else if encoding == UTF_16BE {
+        b"\xFE\xFF"
+    } else {
+        b""
+    }
+}
+
+/// Recognize UTF-16 without a byte order mark by mostly-ASCII text leaving every other
+/// byte NUL
+fn detect_utf16(bytes: &[u8]) -> Option<&'static Encoding> {
+    if bytes.len() < 2 || bytes.len() % 2 != 0 {
+        return None;
+    }
+    let pairs = bytes.len() / 2;
+    let (mut even_nuls, mut odd_nuls) = (0, 0);
+    for pair in bytes.chunks_exact(2) {
+        even_nuls += (pair[0] == 0) as usize;
+        odd_nuls += (pair[1] == 0) as usize;
+    }
+
+    let threshold = pairs * 2 / 3;
+    if odd_nuls > threshold && even_nuls <= pairs / 10 {
+        Some(UTF_16LE)
+    } else if even_nuls > threshold && odd_nuls <= pairs / 10 {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::WINDOWS_1252;
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    fn utf16be(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_be_bytes).collect()
+    }
+
+    #[test]
+    fn test_detect_bom() {
+        let (text, encoding) = TextEncoding::detect(b"\xEF\xBB\xBFh\xC3\xA9");
+        assert_eq!(text, "hé");
+        assert_eq!((encoding.encoding, encoding.has_bom), (UTF_8, true));
+        assert_eq!(encoding.label(), "UTF-8 with BOM");
+
+        let mut bytes = b"\xFF\xFE".to_vec();
+        bytes.extend(utf16le("hé"));
+        let (text, encoding) = TextEncoding::detect(&bytes);
+        assert_eq!(text, "hé");
+        assert_eq!((encoding.encoding, encoding.has_bom), (UTF_16LE, true));
+
+        let mut bytes = b"\xFE\xFF".to_vec();
+        bytes.extend(utf16be("hé"));
+        let (text, encoding) = TextEncoding::detect(&bytes);
+        assert_eq!(text, "hé");
+        assert_eq!((encoding.encoding, encoding.has_bom), (UTF_16BE, true));
+    }
+
+    #[test]
+    fn test_detect_utf16_without_bom() {
+        let (text, encoding) = TextEncoding::detect(&utf16le("plain text\n"));
+        assert_eq!(text, "plain text\n");
+        assert_eq!((encoding.encoding, encoding.has_bom), (UTF_16LE, false));
+
+        let (text, encoding) = TextEncoding::detect(&utf16be("plain text\n"));
+        assert_eq!(text, "plain text\n");
+        assert_eq!((encoding.encoding, encoding.has_bom), (UTF_16BE, false));
+
+        // A few NULs in UTF-8 text don't make it UTF-16
+        let (_, encoding) = TextEncoding::detect(b"ab\0cdefgh");
+        assert_eq!(encoding.encoding, UTF_8);
+        assert_eq!(detect_utf16(b"a\0b"), None);
+    }
+
+    #[test]
+    fn test_detect_fallback() {
+        let (text, encoding) = TextEncoding::detect("héllo wörld".as_bytes());
+        assert_eq!(text, "héllo wörld");
+        assert_eq!((encoding.encoding, encoding.has_bom), (UTF_8, false));
+
+        // Invalid UTF-8 is left to chardetng to guess
+        let bytes = b"Le caf\xE9 est tr\xE8s bon, d\xE9j\xE0 pr\xEAt \xE0 \xEAtre servi.";
+        let (text, encoding) = TextEncoding::detect(bytes);
+        assert_eq!(encoding.encoding, WINDOWS_1252);
+        assert_eq!(text, "Le café est très bon, déjà prêt à être servi.");
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        let text = "héllo\nwörld";
+        for encoding in [UTF_8, UTF_16LE, UTF_16BE, WINDOWS_1252] {
+            for has_bom in [false, true] {
+                let encoding = TextEncoding { encoding, has_bom };
+                let bytes = encoding.encode(text).unwrap();
+                assert_eq!(encoding.decode(&bytes), text);
+                // Reopening keeps the byte order mark, which legacy encodings don't have
+                let reopened = TextEncoding::for_file(encoding.encoding, &bytes);
+                assert_eq!(
+                    reopened.has_bom,
+                    has_bom && encoding.encoding != WINDOWS_1252
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_unrepresentable() {
+        let encoding = TextEncoding::new(WINDOWS_1252);
+        assert!(matches!(
+            encoding.encode("日本"),
+            Err(SaveError::Unencodable { .. })
+        ));
+    }
+}
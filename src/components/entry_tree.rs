@@ -0,0 +1,573 @@
+use crate::components::ignore_rules::IgnoreStack;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Directory,
+    /// A symbolic link, showing where it points. A broken link's target doesn't exist.
+    Symlink {
+        target: PathBuf,
+        is_dir: bool,
+        is_broken: bool,
+    },
+}
+
+/// How much of a directory's contents has been read
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadState {
+    NotLoaded,
+    Loading,
+    Loaded,
+    /// Not read because the directory is a link back to one of its own ancestors
+    Cycle,
+}
+
+/// Identifies a directory on disk however it's reached, as (device, inode)
+pub type FileId = (u64, u64);
+
+/// Identifies an entry in an `EntryTree` for as long as the entry is in the tree
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EntryId(usize);
+
+impl From<EntryId> for gpui::ElementId {
+    fn from(id: EntryId) -> Self {
+        gpui::ElementId::named_usize("entry", id.0)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub name: String,
+    pub kind: EntryKind,
+    pub parent: Option<EntryId>,
+    /// Only filled in once the directory is first expanded
+    pub children: Vec<EntryId>,
+    pub load_state: LoadState,
+    pub is_expanded: bool,
+    /// Matched by an ignore file or exclude glob, or inside a directory that is
+    pub is_ignored: bool,
+    /// For directories, including linked ones, to notice a link leading back up the tree
+    pub file_id: Option<FileId>,
+}
+
+impl Entry {
+    /// Create an entry without reading a directory's contents. Symlinks are described rather
+    /// than followed.
+    pub fn from_path(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let path = path.as_ref();
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("")
+            .to_string();
+
+        let metadata = std::fs::symlink_metadata(path)?;
+        let (kind, file_id) = if metadata.is_symlink() {
+            let target = std::fs::read_link(path)?;
+            match std::fs::metadata(path) {
+                Ok(target_metadata) => {
+                    let is_dir = target_metadata.is_dir();
+                    let kind = EntryKind::Symlink {
+                        target,
+                        is_dir,
+                        is_broken: false,
+                    };
+                    (kind, is_dir.then(|| file_id(&target_metadata)).flatten())
+                }
+                Err(_) => {
+                    let kind = EntryKind::Symlink {
+                        target,
+                        is_dir: false,
+                        is_broken: true,
+                    };
+                    (kind, None)
+                }
+            }
+        } else if metadata.is_dir() {
+            (EntryKind::Directory, file_id(&metadata))
+        } else {
+            (EntryKind::File, None)
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            name,
+            kind,
+            parent: None,
+            children: Vec::new(),
+            load_state: LoadState::NotLoaded,
+            is_expanded: false,
+            is_ignored: false,
+            file_id,
+        })
+    }
+
+    /// Read a directory's immediate children, sorted for display. `ignores` holds the rules
+    /// for the directory being read.
+    pub fn read_children(
+        path: &Path,
+        ignores: &IgnoreStack,
+        parent_ignored: bool,
+    ) -> std::io::Result<Vec<Entry>> {
+        let mut children = std::fs::read_dir(path)?
+            .flatten()
+            .filter_map(|entry| Self::from_path(entry.path()).ok())
+            .map(|mut child| {
+                child.is_ignored =
+                    parent_ignored || ignores.is_ignored(&child.path, child.is_dir());
+                child
+            })
+            .collect::<Vec<_>>();
+        sort_entries(&mut children);
+        Ok(children)
+    }
+
+    /// Whether the entry is a directory or a link to one
+    pub fn is_dir(&self) -> bool {
+        matches!(
+            self.kind,
+            EntryKind::Directory | EntryKind::Symlink { is_dir: true, .. }
+        )
+    }
+
+    /// Whether the entry is a file or a link to one that exists
+    pub fn is_file(&self) -> bool {
+        match &self.kind {
+            EntryKind::File => true,
+            EntryKind::Directory => false,
+            EntryKind::Symlink {
+                is_dir, is_broken, ..
+            } => !is_dir && !is_broken,
+        }
+    }
+
+    /// Linked directories only open when following symlinks is turned on
+    pub fn can_expand(&self, follow_symlinks: bool) -> bool {
+        self.is_dir() && (follow_symlinks || !self.is_symlink())
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        matches!(self.kind, EntryKind::Symlink { .. })
+    }
+
+    /// Whether the entry is only listed when hidden files are shown
+    pub fn is_hidden(&self) -> bool {
+        self.is_ignored || self.name.starts_with('.')
+    }
+}
+
+#[cfg(unix)]
+pub fn file_id(metadata: &std::fs::Metadata) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+pub fn file_id(_metadata: &std::fs::Metadata) -> Option<FileId> {
+    None
+}
+
+/// Sort directories first, then files, alphabetically
+fn sort_entries(entries: &mut [Entry]) {
+    entries.sort_by(|a, b| match (a.is_dir(), b.is_dir()) {
+        (true, false) => std::cmp::Ordering::Less,
+        (false, true) => std::cmp::Ordering::Greater,
+        _ => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+    });
+}
+
+/// The worktree's entries, stored flat and linked to each other by id
+pub struct EntryTree {
+    /// Indexed by `EntryId`. A removed entry leaves its slot empty until it's reused.
+    entries: Vec<Option<Entry>>,
+    free: Vec<EntryId>,
+    ids_by_path: HashMap<PathBuf, EntryId>,
+    root: EntryId,
+}
+
+impl EntryTree {
+    pub fn new(root: Entry) -> Self {
+        let mut tree = Self {
+            entries: Vec::new(),
+            free: Vec::new(),
+            ids_by_path: HashMap::new(),
+            root: EntryId(0),
+        };
+        tree.root = tree.insert(root, None);
+        tree
+    }
+
+    pub fn root(&self) -> EntryId {
+        self.root
+    }
+
+    pub fn get(&self, id: EntryId) -> Option<&Entry> {
+        self.entries.get(id.0).and_then(Option::as_ref)
+    }
+
+    pub fn get_mut(&mut self, id: EntryId) -> Option<&mut Entry> {
+        self.entries.get_mut(id.0).and_then(Option::as_mut)
+    }
+
+    pub fn id_for_path(&self, path: &Path) -> Option<EntryId> {
+        self.ids_by_path.get(path).copied()
+    }
+
+    pub fn entry_for_path(&self, path: &Path) -> Option<&Entry> {
+        self.get(self.id_for_path(path)?)
+    }
+
+    pub fn entry_for_path_mut(&mut self, path: &Path) -> Option<&mut Entry> {
+        self.get_mut(self.id_for_path(path)?)
+    }
+
+    fn insert(&mut self, mut entry: Entry, parent: Option<EntryId>) -> EntryId {
+        entry.parent = parent;
+        entry.children.clear();
+        let id = match self.free.pop() {
+            Some(id) => id,
+            None => {
+                self.entries.push(None);
+                EntryId(self.entries.len() - 1)
+            }
+        };
+        self.ids_by_path.insert(entry.path.clone(), id);
+        self.entries[id.0] = Some(entry);
+        id
+    }
+
+    /// Remove an entry and everything below it
+    fn remove(&mut self, id: EntryId) {
+        let Some(entry) = self.entries.get_mut(id.0).and_then(Option::take) else {
+            return;
+        };
+        if self.ids_by_path.get(&entry.path) == Some(&id) {
+            self.ids_by_path.remove(&entry.path);
+        }
+        self.free.push(id);
+        for child in entry.children {
+            self.remove(child);
+        }
+    }
+
+    /// Replace a directory's children with a fresh listing. Children that are still there
+    /// keep their ids and subtrees, so their expansion state survives.
+    pub fn set_children(&mut self, parent: EntryId, listing: Vec<Entry>) {
+        let Some(old_children) = self
+            .get_mut(parent)
+            .map(|entry| std::mem::take(&mut entry.children))
+        else {
+            return;
+        };
+        let mut kept = HashSet::new();
+        let mut children = Vec::with_capacity(listing.len());
+        for new_child in listing {
+            let existing = self.id_for_path(&new_child.path).filter(|id| {
+                self.get(*id)
+                    .is_some_and(|entry| entry.parent == Some(parent))
+            });
+            let id = match existing {
+                Some(id)
+                    if self
+                        .get(id)
+                        .is_some_and(|entry| entry.kind == new_child.kind) =>
+                {
+                    if let Some(entry) = self.get_mut(id) {
+                        entry.is_ignored = new_child.is_ignored;
+                    }
+                    id
+                }
+                // Something else has taken the name, so what was there goes
+                Some(id) => {
+                    self.remove(id);
+                    self.insert(new_child, Some(parent))
+                }
+                None => self.insert(new_child, Some(parent)),
+            };
+            kept.insert(id);
+            children.push(id);
+        }
+        for id in old_children {
+            if !kept.contains(&id) {
+                self.remove(id);
+            }
+        }
+        if let Some(entry) = self.get_mut(parent) {
+            entry.children = children;
+        }
+    }
+
+    /// Re-read a loaded directory's listing. Every child's ignore status is rechecked.
+    pub fn rescan_children(&mut self, id: EntryId, ignores: &IgnoreStack) {
+        let Some(entry) = self.get(id) else {
+            return;
+        };
+        if entry.load_state != LoadState::Loaded {
+            return;
+        }
+        if let Ok(listing) = Entry::read_children(&entry.path, ignores, entry.is_ignored) {
+            self.set_children(id, listing);
+        }
+    }
+
+    /// Re-read every loaded directory from this one down
+    pub fn rescan_loaded(&mut self, id: EntryId, ignores: &IgnoreStack) {
+        self.rescan_children(id, ignores);
+        let children = self
+            .get(id)
+            .map(|entry| entry.children.clone())
+            .unwrap_or_default();
+        for child in children {
+            let Some(entry) = self.get(child) else {
+                continue;
+            };
+            if entry.load_state == LoadState::Loaded {
+                let ignores = ignores.child(&entry.path);
+                self.rescan_loaded(child, &ignores);
+            }
+        }
+    }
+
+    /// The ids of the directories above an entry
+    pub fn ancestor_ids(&self, id: EntryId) -> Vec<FileId> {
+        let mut ids = Vec::new();
+        let mut parent = self.get(id).and_then(|entry| entry.parent);
+        while let Some(entry) = parent.and_then(|id| self.get(id)) {
+            ids.extend(entry.file_id);
+            parent = entry.parent;
+        }
+        ids
+    }
+
+    /// The entries shown in the tree, top to bottom, with how deep each one is. Only the
+    /// children of expanded directories are included.
+    pub fn visible_entries(&self, show_hidden: bool) -> Vec<(EntryId, usize)> {
+        let mut visible = Vec::new();
+        let mut stack = vec![(self.root, 0)];
+        while let Some((id, depth)) = stack.pop() {
+            let Some(entry) = self.get(id) else {
+                continue;
+            };
+            visible.push((id, depth));
+            if entry.is_dir() && entry.is_expanded {
+                let children = entry.children.iter().rev().filter(|child| {
+                    self.get(**child)
+                        .is_some_and(|child| show_hidden || !child.is_hidden())
+                });
+                stack.extend(children.map(|child| (*child, depth + 1)));
+            }
+        }
+        visible
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, kind: EntryKind) -> Entry {
+        let path = PathBuf::from(path);
+        Entry {
+            name: path.file_name().unwrap().to_string_lossy().into_owned(),
+            path,
+            kind,
+            parent: None,
+            children: Vec::new(),
+            load_state: LoadState::NotLoaded,
+            is_expanded: false,
+            is_ignored: false,
+            file_id: None,
+        }
+    }
+
+    fn dir(path: &str) -> Entry {
+        entry(path, EntryKind::Directory)
+    }
+
+    fn file(path: &str) -> Entry {
+        entry(path, EntryKind::File)
+    }
+
+    fn expand(tree: &mut EntryTree, path: &str) -> EntryId {
+        let id = tree.id_for_path(Path::new(path)).unwrap();
+        let entry = tree.get_mut(id).unwrap();
+        entry.is_expanded = true;
+        entry.load_state = LoadState::Loaded;
+        id
+    }
+
+    fn visible_paths(tree: &EntryTree, show_hidden: bool) -> Vec<(String, usize)> {
+        tree.visible_entries(show_hidden)
+            .into_iter()
+            .map(|(id, depth)| {
+                let path = tree.get(id).unwrap().path.to_string_lossy().into_owned();
+                (path, depth)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_set_children_keeps_ids() {
+        let mut tree = EntryTree::new(dir("/r"));
+        let root = expand(&mut tree, "/r");
+        tree.set_children(root, vec![dir("/r/a"), dir("/r/b"), file("/r/c")]);
+        let a = expand(&mut tree, "/r/a");
+        tree.set_children(a, vec![file("/r/a/x")]);
+        let x = tree.id_for_path(Path::new("/r/a/x")).unwrap();
+
+        // A rescan that adds an entry and turns b into a file
+        let mut listing = vec![dir("/r/a"), file("/r/b"), file("/r/c"), file("/r/d")];
+        listing[0].is_ignored = true;
+        tree.set_children(root, listing);
+
+        assert_eq!(tree.id_for_path(Path::new("/r/a")), Some(a));
+        let entry = tree.get(a).unwrap();
+        assert!(entry.is_expanded);
+        assert!(entry.is_ignored);
+        assert_eq!(entry.children, vec![x]);
+        assert_eq!(tree.get(x).unwrap().parent, Some(a));
+        let b_entry = tree.entry_for_path(Path::new("/r/b")).unwrap();
+        assert_eq!(b_entry.kind, EntryKind::File);
+        assert!(b_entry.children.is_empty());
+        assert_eq!(
+            visible_paths(&tree, true),
+            [
+                ("/r", 0),
+                ("/r/a", 1),
+                ("/r/a/x", 2),
+                ("/r/b", 1),
+                ("/r/c", 1),
+                ("/r/d", 1)
+            ]
+            .map(|(path, depth)| (path.to_string(), depth))
+        );
+
+        // Removed entries take their subtrees with them
+        tree.set_children(root, vec![file("/r/c")]);
+        for path in ["/r/a", "/r/a/x", "/r/b", "/r/d"] {
+            assert_eq!(tree.id_for_path(Path::new(path)), None, "{path}");
+        }
+        assert_eq!(
+            visible_paths(&tree, true),
+            [("/r".to_string(), 0), ("/r/c".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn test_visible_entries_order() {
+        let mut tree = EntryTree::new(dir("/r"));
+        assert_eq!(visible_paths(&tree, true), [("/r".to_string(), 0)]);
+
+        let root = expand(&mut tree, "/r");
+        tree.set_children(
+            root,
+            vec![dir("/r/a"), dir("/r/b"), dir("/r/.hidden"), file("/r/z")],
+        );
+        let a = expand(&mut tree, "/r/a");
+        tree.set_children(a, vec![dir("/r/a/inner"), file("/r/a/x")]);
+        let inner = expand(&mut tree, "/r/a/inner");
+        tree.set_children(inner, vec![file("/r/a/inner/deep")]);
+        // Loaded but collapsed, so its children stay out of view
+        let b = expand(&mut tree, "/r/b");
+        tree.set_children(b, vec![file("/r/b/y")]);
+        tree.get_mut(b).unwrap().is_expanded = false;
+        let mut ignored = file("/r/a/ignored");
+        ignored.is_ignored = true;
+        tree.set_children(a, vec![dir("/r/a/inner"), ignored, file("/r/a/x")]);
+
+        let expected = |paths: &[(&str, usize)]| {
+            paths
+                .iter()
+                .map(|(path, depth)| (path.to_string(), *depth))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            visible_paths(&tree, false),
+            expected(&[
+                ("/r", 0),
+                ("/r/a", 1),
+                ("/r/a/inner", 2),
+                ("/r/a/inner/deep", 3),
+                ("/r/a/x", 2),
+                ("/r/b", 1),
+                ("/r/z", 1),
+            ])
+        );
+        assert_eq!(
+            visible_paths(&tree, true),
+            expected(&[
+                ("/r", 0),
+                ("/r/a", 1),
+                ("/r/a/inner", 2),
+                ("/r/a/inner/deep", 3),
+                ("/r/a/ignored", 2),
+                ("/r/a/x", 2),
+                ("/r/b", 1),
+                ("/r/.hidden", 1),
+                ("/r/z", 1),
+            ])
+        );
+
+        // Collapsing a parent hides everything below it, however deep
+        tree.get_mut(a).unwrap().is_expanded = false;
+        assert_eq!(
+            visible_paths(&tree, false),
+            expected(&[("/r", 0), ("/r/a", 1), ("/r/b", 1), ("/r/z", 1)])
+        );
+    }
+
+    #[test]
+    fn test_reused_ids_start_empty() {
+        let mut tree = EntryTree::new(dir("/r"));
+        let root = expand(&mut tree, "/r");
+        tree.set_children(root, vec![dir("/r/a")]);
+        let a = expand(&mut tree, "/r/a");
+        tree.set_children(a, vec![dir("/r/a/x"), file("/r/a/y")]);
+        let x = expand(&mut tree, "/r/a/x");
+        tree.set_children(x, vec![file("/r/a/x/z")]);
+        let old_ids = [a, x]
+            .into_iter()
+            .chain(tree.get(a).unwrap().children.clone())
+            .chain(tree.get(x).unwrap().children.clone())
+            .collect::<HashSet<_>>();
+
+        tree.set_children(root, Vec::new());
+        for path in ["/r/a", "/r/a/x", "/r/a/y", "/r/a/x/z"] {
+            assert_eq!(tree.id_for_path(Path::new(path)), None, "{path}");
+        }
+
+        // New entries take the freed slots, even when given a stale child list
+        let mut b = dir("/r/b");
+        b.children = vec![x];
+        tree.set_children(root, vec![b, dir("/r/c"), file("/r/d"), file("/r/e")]);
+        let new_ids = tree.get(root).unwrap().children.clone();
+        assert!(new_ids.iter().all(|id| old_ids.contains(id)));
+        for id in &new_ids {
+            let entry = tree.get(*id).unwrap();
+            assert!(entry.children.is_empty(), "{:?}", entry.path);
+            assert_eq!(entry.parent, Some(root));
+            assert!(!entry.is_expanded);
+        }
+
+        let b = expand(&mut tree, "/r/b");
+        let c = expand(&mut tree, "/r/c");
+        assert_eq!(
+            visible_paths(&tree, true),
+            [
+                ("/r", 0),
+                ("/r/b", 1),
+                ("/r/c", 1),
+                ("/r/d", 1),
+                ("/r/e", 1)
+            ]
+            .map(|(path, depth)| (path.to_string(), depth))
+        );
+        tree.set_children(b, vec![file("/r/b/w")]);
+        let w = tree.id_for_path(Path::new("/r/b/w")).unwrap();
+        assert_eq!(tree.get(w).unwrap().parent, Some(b));
+        assert!(tree.get(c).unwrap().children.is_empty());
+    }
+}
@@ -0,0 +1,194 @@
+use std::path::{Path, PathBuf};
+
+/// Check a name typed for a new or renamed entry
+pub fn validate_name(name: &str) -> std::io::Result<()> {
+    let invalid = |message: &str| {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            message.to_string(),
+        ))
+    };
+    if name.trim().is_empty() {
+        return invalid("Name can't be empty");
+    }
+    if name == "." || name == ".." {
+        return invalid("Name can't be . or ..");
+    }
+    if name.contains('/') || name.contains(std::path::MAIN_SEPARATOR) {
+        return invalid("Name can't contain a path separator");
+    }
+    Ok(())
+}
+
+/// Create an empty file, failing if the path is taken
+pub fn create_file(path: &Path) -> std::io::Result<()> {
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .map(|_| ())
+}
+
+/// Rename or move an entry, failing rather than replacing something already at `to`
+pub fn rename(from: &Path, to: &Path) -> std::io::Result<()> {
+    if to.symlink_metadata().is_ok() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("{} already exists", to.display()),
+        ));
+    }
+    std::fs::rename(from, to)
+}
+
+/// Copy an entry next to itself as `name copy.ext`, `name copy 2.ext` and so on, returning
+/// the new path
+pub fn duplicate(path: &Path) -> std::io::Result<PathBuf> {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    copy_into(path, dir)
+}
+
+/// Copy an entry into a directory, keeping its name unless that's taken, and return the
+/// new path
+pub fn copy_into(path: &Path, dir: &Path) -> std::io::Result<PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "No file name"))?;
+    if dir.starts_with(path) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Can't copy a folder into itself",
+        ));
+    }
+    let mut target = dir.join(name);
+    if target.symlink_metadata().is_ok() {
+        target = copy_name(&target, path.is_dir());
+    }
+    copy_recursive(path, &target)?;
+    Ok(target)
+}
+
+/// Move an entry into a directory, keeping its name, and return the new path
+pub fn move_into(path: &Path, dir: &Path) -> std::io::Result<PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "No file name"))?;
+    if dir.starts_with(path) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Can't move a folder into itself",
+        ));
+    }
+    let target = dir.join(name);
+    // Dropping an entry back into its own folder leaves it where it is
+    if target == path {
+        return Ok(target);
+    }
+    rename(path, &target)?;
+    Ok(target)
+}
+
+/// The first free `copy` name beside a path
+fn copy_name(path: &Path, is_dir: bool) -> PathBuf {
+    let stem = if is_dir {
+        path.file_name()
+    } else {
+        path.file_stem()
+    }
+    .map(|stem| stem.to_string_lossy().into_owned())
+    .unwrap_or_default();
+    let extension = path
+        .extension()
+        .filter(|_| !is_dir)
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+
+    let mut attempt = 1;
+    loop {
+        let name = if attempt == 1 {
+            format!("{} copy{}", stem, extension)
+        } else {
+            format!("{} copy {}{}", stem, attempt, extension)
+        };
+        let candidate = path.with_file_name(name);
+        if candidate.symlink_metadata().is_err() {
+            return candidate;
+        }
+        attempt += 1;
+    }
+}
+
+/// Copy a file, or a directory and everything in it. Symlinks are copied as links.
+pub fn copy_recursive(from: &Path, to: &Path) -> std::io::Result<()> {
+    let metadata = from.symlink_metadata()?;
+    if metadata.is_symlink() {
+        copy_symlink(from, to)
+    } else if metadata.is_dir() {
+        std::fs::create_dir(to)?;
+        for entry in std::fs::read_dir(from)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        std::fs::set_permissions(to, metadata.permissions())
+    } else {
+        std::fs::copy(from, to).map(|_| ())
+    }
+}
+
+#[cfg(unix)]
+fn copy_symlink(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(std::fs::read_link(from)?, to)
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::copy(from, to).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::test_support::TempDir;
+
+    #[test]
+    fn test_into_itself() {
+        let dir = TempDir::new("file-ops-itself");
+        let folder = dir.join("folder");
+        let inner = folder.join("inner");
+        std::fs::create_dir_all(&inner).unwrap();
+
+        for target in [&folder, &inner] {
+            let err = copy_into(&folder, target).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+            let err = move_into(&folder, target).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        }
+        assert!(inner.is_dir());
+        assert!(!inner.join("folder").exists());
+
+        // A sibling whose name starts the same isn't inside it
+        let sibling = dir.join("folder2");
+        std::fs::create_dir(&sibling).unwrap();
+        assert_eq!(
+            move_into(&folder, &sibling).unwrap(),
+            sibling.join("folder")
+        );
+    }
+
+    #[test]
+    fn test_duplicate() {
+        let dir = TempDir::new("file-ops-duplicate");
+        let file = dir.join("notes.txt");
+        std::fs::write(&file, "hello").unwrap();
+
+        assert_eq!(duplicate(&file).unwrap(), dir.join("notes copy.txt"));
+        assert_eq!(duplicate(&file).unwrap(), dir.join("notes copy 2.txt"));
+        assert_eq!(
+            std::fs::read_to_string(dir.join("notes copy 2.txt")).unwrap(),
+            "hello"
+        );
+
+        let folder = dir.join("folder.d");
+        std::fs::create_dir(&folder).unwrap();
+        assert_eq!(duplicate(&folder).unwrap(), dir.join("folder.d copy"));
+    }
+}
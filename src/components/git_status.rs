@@ -0,0 +1,277 @@
+use git2::{Repository, Status, StatusOptions};
+use gpui::{rgb, Rgba};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// How a file differs from what the repository has recorded
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum GitStatus {
+    Ignored,
+    Untracked,
+    Added,
+    Renamed,
+    Modified,
+    /// Left with merge conflicts to resolve
+    Conflicted,
+}
+
+impl GitStatus {
+    /// Read the status flags git reports for one file
+    fn from_flags(flags: Status) -> Option<Self> {
+        if flags.is_conflicted() {
+            Some(Self::Conflicted)
+        } else if flags.intersects(Status::INDEX_RENAMED | Status::WT_RENAMED) {
+            Some(Self::Renamed)
+        } else if flags.is_index_new() {
+            Some(Self::Added)
+        } else if flags.is_wt_new() {
+            Some(Self::Untracked)
+        } else if flags.intersects(
+            Status::INDEX_MODIFIED
+                | Status::WT_MODIFIED
+                | Status::INDEX_TYPECHANGE
+                | Status::WT_TYPECHANGE
+                | Status::INDEX_DELETED
+                | Status::WT_DELETED,
+        ) {
+            Some(Self::Modified)
+        } else if flags.is_ignored() {
+            Some(Self::Ignored)
+        } else {
+            None
+        }
+    }
+
+    /// The letter shown beside an entry with this status
+    pub fn letter(self) -> &'static str {
+        match self {
+            Self::Ignored => "I",
+            Self::Untracked => "U",
+            Self::Added => "A",
+            Self::Renamed => "R",
+            Self::Modified => "M",
+            Self::Conflicted => "C",
+        }
+    }
+
+    /// How the status is described in the editor header
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Ignored => "Ignored",
+            Self::Untracked => "Untracked",
+            Self::Added => "Added",
+            Self::Renamed => "Renamed",
+            Self::Modified => "Modified",
+            Self::Conflicted => "Conflicted",
+        }
+    }
+
+    pub fn color(self) -> Rgba {
+        match self {
+            Self::Ignored => rgb(0x999999),
+            Self::Untracked => rgb(0x2f9e44),
+            Self::Added => rgb(0x2f9e44),
+            Self::Renamed => rgb(0x1c7ed6),
+            Self::Modified => rgb(0xd9822b),
+            Self::Conflicted => rgb(0xcc0000),
+        }
+    }
+}
+
+/// The status of every changed file in the repository containing a worktree, as of when it
+/// was read. Paths are looked up the way the worktree spells them.
+#[derive(Default)]
+pub struct GitStatuses {
+    root: PathBuf,
+    /// The root with symlinks resolved, for paths that arrive spelled that way
+    canonical_root: PathBuf,
+    /// Where the worktree root is within the repository
+    root_in_repo: PathBuf,
+    /// Keyed by path relative to the repository
+    files: HashMap<PathBuf, GitStatus>,
+    /// The most pressing status of anything changed inside each directory
+    dirs: HashMap<PathBuf, GitStatus>,
+}
+
+impl GitStatuses {
+    /// Read the status of the repository containing `root`. Outside a repository there's
+    /// nothing to report.
+    pub fn load(root: &Path) -> Self {
+        Self::read(root).unwrap_or_else(|_| Self {
+            root: root.to_path_buf(),
+            canonical_root: root.canonicalize().unwrap_or_else(|_| root.to_path_buf()),
+            ..Default::default()
+        })
+    }
+
+    fn read(root: &Path) -> Result<Self, git2::Error> {
+        let repo = Repository::discover(root)?;
+        let Some(workdir) = repo.workdir() else {
+            return Err(git2::Error::from_str("bare repository"));
+        };
+        let canonical_root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+        let workdir = workdir
+            .canonicalize()
+            .unwrap_or_else(|_| workdir.to_path_buf());
+        let root_in_repo = canonical_root
+            .strip_prefix(&workdir)
+            .unwrap_or(Path::new(""))
+            .to_path_buf();
+
+        let mut options = StatusOptions::new();
+        options
+            .include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .include_ignored(true)
+            .recurse_ignored_dirs(false)
+            .renames_head_to_index(true)
+            .renames_index_to_workdir(true);
+
+        let mut statuses = Self {
+            root: root.to_path_buf(),
+            canonical_root,
+            root_in_repo,
+            ..Default::default()
+        };
+        for entry in repo.statuses(Some(&mut options))?.iter() {
+            let Some(status) = GitStatus::from_flags(entry.status()) else {
+                continue;
+            };
+            // A renamed file is listed under its old name, so find where it went
+            let path = entry
+                .index_to_workdir()
+                .or_else(|| entry.head_to_index())
+                .and_then(|delta| delta.new_file().path().map(Path::to_path_buf))
+                .or_else(|| entry.path().map(PathBuf::from));
+            let Some(path) = path else {
+                continue;
+            };
+            // Ignored directories are reported once, with a trailing slash
+            let path = path.components().collect::<PathBuf>();
+            statuses.insert(path, status);
+        }
+        Ok(statuses)
+    }
+
+    fn insert(&mut self, path: PathBuf, status: GitStatus) {
+        if status != GitStatus::Ignored {
+            for dir in path.ancestors().skip(1) {
+                let dir_status = self.dirs.entry(dir.to_path_buf()).or_insert(status);
+                *dir_status = (*dir_status).max(status);
+            }
+        }
+        self.files.insert(path, status);
+    }
+
+    /// The status of a file, or for a directory, the most pressing status of what's inside.
+    /// Anything inside an ignored directory is ignored too.
+    pub fn status(&self, path: &Path, is_dir: bool) -> Option<GitStatus> {
+        let relative = path
+            .strip_prefix(&self.root)
+            .or_else(|_| path.strip_prefix(&self.canonical_root))
+            .ok()?;
+        let relative = self.root_in_repo.join(relative);
+        let ignored = relative
+            .ancestors()
+            .any(|ancestor| self.files.get(ancestor) == Some(&GitStatus::Ignored));
+        if ignored {
+            Some(GitStatus::Ignored)
+        } else if is_dir {
+            self.dirs.get(&relative).copied()
+        } else {
+            self.files.get(&relative).copied()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::test_support::TempDir;
+
+    fn write(root: &Path, path: &str, text: &str) {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, text).unwrap();
+    }
+
+    fn stage(repo: &Repository, paths: &[&str]) -> git2::Oid {
+        let mut index = repo.index().unwrap();
+        for path in paths {
+            index.add_path(Path::new(path)).unwrap();
+        }
+        index.write().unwrap();
+        index.write_tree().unwrap()
+    }
+
+    fn commit(repo: &Repository, paths: &[&str]) {
+        let tree = repo.find_tree(stage(repo, paths)).unwrap();
+        let signature = git2::Signature::now("Test", "test@example.com").unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "Initial", &tree, &[])
+            .unwrap();
+    }
+
+    #[test]
+    fn test_directories_take_worst_status() {
+        let dir = TempDir::new("git-dirs");
+        let repo = Repository::init(&*dir).unwrap();
+        write(&dir, "src/main.rs", "fn main() {}\n");
+        write(&dir, "src/lib.rs", "\n");
+        write(&dir, "clean/a.txt", "a\n");
+        commit(&repo, &["src/main.rs", "src/lib.rs", "clean/a.txt"]);
+
+        write(&dir, "src/main.rs", "fn main() { todo!() }\n");
+        write(&dir, "src/new.rs", "\n");
+        write(&dir, "src/deep/added.rs", "\n");
+        write(&dir, "src/deep/untracked.rs", "\n");
+        stage(&repo, &["src/deep/added.rs"]);
+        write(&dir, "other/untracked.txt", "\n");
+
+        let statuses = GitStatuses::load(&dir);
+        let status = |path: &str, is_dir| statuses.status(&dir.join(path), is_dir);
+        assert_eq!(status("src/main.rs", false), Some(GitStatus::Modified));
+        assert_eq!(status("src/lib.rs", false), None);
+        assert_eq!(status("src/new.rs", false), Some(GitStatus::Untracked));
+        assert_eq!(status("src/deep/added.rs", false), Some(GitStatus::Added));
+        assert_eq!(status("src/deep", true), Some(GitStatus::Added));
+        assert_eq!(status("src", true), Some(GitStatus::Modified));
+        assert_eq!(status("other", true), Some(GitStatus::Untracked));
+        assert_eq!(status("clean", true), None);
+        assert_eq!(status("", true), Some(GitStatus::Modified));
+
+        // A worktree opened on a subdirectory looks paths up relative to the repository
+        let statuses = GitStatuses::load(&dir.join("src"));
+        let status = |path: &str, is_dir| statuses.status(&dir.join(path), is_dir);
+        assert_eq!(status("src/deep", true), Some(GitStatus::Added));
+        assert_eq!(status("src/main.rs", false), Some(GitStatus::Modified));
+        assert_eq!(status("src", true), Some(GitStatus::Modified));
+    }
+
+    #[test]
+    fn test_ignored_status_is_inherited() {
+        let dir = TempDir::new("git-ignored");
+        let repo = Repository::init(&*dir).unwrap();
+        write(&dir, ".gitignore", "build/\n*.log\n");
+        write(&dir, "README", "\n");
+        commit(&repo, &[".gitignore", "README"]);
+
+        write(&dir, "build/out/app.o", "\n");
+        write(&dir, "logs/today.log", "\n");
+        write(&dir, "logs/notes.txt", "\n");
+        write(&dir, "quiet/only.log", "\n");
+
+        let statuses = GitStatuses::load(&dir);
+        let status = |path: &str, is_dir| statuses.status(&dir.join(path), is_dir);
+        assert_eq!(status("build", true), Some(GitStatus::Ignored));
+        assert_eq!(status("build/out", true), Some(GitStatus::Ignored));
+        assert_eq!(status("build/out/app.o", false), Some(GitStatus::Ignored));
+        assert_eq!(status("logs/today.log", false), Some(GitStatus::Ignored));
+        assert_eq!(status("logs/notes.txt", false), Some(GitStatus::Untracked));
+        assert_eq!(status("logs", true), Some(GitStatus::Untracked));
+        // Ignored files don't mark the directory holding them
+        assert_eq!(status("quiet/only.log", false), Some(GitStatus::Ignored));
+        assert_eq!(status("quiet", true), None);
+        assert_eq!(status("README", false), None);
+        assert_eq!(status("", true), Some(GitStatus::Untracked));
+    }
+}
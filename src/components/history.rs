@@ -0,0 +1,527 @@
+use crate::components::{ByteOffset, Selection};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Transactions closer together than this are undone as one
+const GROUP_INTERVAL: Duration = Duration::from_millis(300);
+
+/// Identifies a state in the undo tree. The original text is always `0`.
+pub type TransactionId = usize;
+
+/// A single change to a buffer's text
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Edit {
+    Insert { pos: ByteOffset, text: String },
+    Remove { pos: ByteOffset, text: String },
+}
+
+/// What made a transaction, which decides whether the next one can be merged into it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EditKind {
+    /// Text typed at the cursor
+    Typing,
+    /// Text deleted with backspace or delete
+    Deletion,
+    /// Text composed with an input method, one step from the first marked text until
+    /// it's committed however long that takes
+    Composition,
+    /// Anything else, such as a paste or cut, which is always its own step
+    #[default]
+    Other,
+}
+
+/// A group of edits that are undone and redone together
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Transaction {
+    /// Edits in the order they were applied
+    pub edits: Vec<Edit>,
+    #[serde(default)]
+    pub kind: EditKind,
+    /// The selection before the first edit, restored on undo
+    pub selection_before: Selection,
+    /// The selection after the last edit, restored on redo
+    pub selection_after: Selection,
+    /// When the last edit was added, for grouping with the next transaction
+    #[serde(skip, default = "Instant::now")]
+    last_edited_at: Instant,
+}
+
+impl Transaction {
+    fn new(selection: &Selection, kind: EditKind) -> Self {
+        Self {
+            edits: Vec::new(),
+            kind,
+            selection_before: selection.clone(),
+            selection_after: selection.clone(),
+            last_edited_at: Instant::now(),
+        }
+    }
+
+    /// Describe the transaction in a few words
+    pub fn summary(&self) -> String {
+        let inserted = self
+            .edits
+            .iter()
+            .map(|edit| match edit {
+                Edit::Insert { text, .. } => Some(text.as_str()),
+                Edit::Remove { .. } => None,
+            })
+            .collect::<Option<String>>();
+
+        match (self.edits.as_slice(), inserted) {
+            ([], _) => "Original".to_string(),
+            (_, Some(text)) => format!("Insert {}", preview(&text)),
+            ([Edit::Remove { text, .. }], _) => format!("Delete {}", preview(text)),
+            (edits, None) => format!("{} edits", edits.len()),
+        }
+    }
+}
+
+/// Quote the start of some text on a single line
+fn preview(text: &str) -> String {
+    const MAX_CHARS: usize = 24;
+    let mut preview = text.replace('\n', "⏎").replace('\t', "→");
+    if let Some((ix, _)) = preview.char_indices().nth(MAX_CHARS) {
+        preview.truncate(ix);
+        preview.push('…');
+    }
+    format!("\"{}\"", preview)
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Node {
+    parent: Option<TransactionId>,
+    /// Branches made from this state, oldest first
+    children: Vec<TransactionId>,
+    /// The child redo moves to: the one most recently undone or created
+    active_child: Option<TransactionId>,
+    transaction: Transaction,
+    created_at: SystemTime,
+}
+
+/// A row of the history panel
+#[derive(Clone, Debug)]
+pub struct HistoryEntry {
+    pub id: TransactionId,
+    /// How many branches deep the state is
+    pub depth: usize,
+    pub created_at: SystemTime,
+    pub summary: String,
+    pub is_current: bool,
+}
+
+/// An undo tree. Editing after an undo starts a new branch, so no state is ever lost.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct History {
+    nodes: Vec<Node>,
+    /// The state the buffer is in
+    current: TransactionId,
+    /// The transaction being built, and how many times it has been started
+    #[serde(skip)]
+    pending: Option<(Transaction, usize)>,
+    /// Whether the next transaction may be merged into the current one
+    #[serde(skip)]
+    can_group: bool,
+    /// The state matching the file on disk, if it's still in the tree
+    #[serde(skip)]
+    saved: Option<TransactionId>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self {
+            nodes: vec![Node {
+                parent: None,
+                children: Vec::new(),
+                active_child: None,
+                transaction: Transaction::new(&Selection::cursor(ByteOffset(0)), EditKind::Other),
+                created_at: SystemTime::now(),
+            }],
+            current: 0,
+            pending: None,
+            can_group: false,
+            saved: Some(0),
+        }
+    }
+
+    /// Start a transaction, or nest inside the one already started. The outermost
+    /// transaction decides the kind.
+    pub fn start_transaction(&mut self, selection: &Selection, kind: EditKind) {
+        match &mut self.pending {
+            Some((_, depth)) => *depth += 1,
+            None => self.pending = Some((Transaction::new(selection, kind), 1)),
+        }
+    }
+
+    /// End a transaction. Once the outermost one ends it becomes the current state.
+    pub fn end_transaction(&mut self, selection: &Selection) {
+        let Some((transaction, depth)) = &mut self.pending else {
+            return;
+        };
+        *depth -= 1;
+        if *depth > 0 {
+            return;
+        }
+        transaction.selection_after = selection.clone();
+        if let Some((transaction, _)) = self.pending.take() {
+            self.push_transaction(transaction);
+        }
+    }
+
+    /// Record an edit, as its own transaction if none has been started
+    pub fn push(&mut self, edit: Edit) {
+        match &mut self.pending {
+            Some((transaction, _)) => {
+                transaction.edits.push(edit);
+                transaction.last_edited_at = Instant::now();
+            }
+            None => {
+                let (before, after) = match &edit {
+                    Edit::Insert { pos, text } => (*pos, *pos + text.len()),
+                    Edit::Remove { pos, .. } => (*pos, *pos),
+                };
+                let mut transaction = Transaction::new(&Selection::cursor(before), EditKind::Other);
+                transaction.edits.push(edit);
+                transaction.selection_after = Selection::cursor(after);
+                self.push_transaction(transaction);
+            }
+        }
+    }
+
+    /// Add a finished transaction as a child of the current state, or merge it into the
+    /// current state if it's more typing or deleting that quickly continues it and nothing
+    /// branches from there yet
+    fn push_transaction(&mut self, transaction: Transaction) {
+        if transaction.edits.is_empty() {
+            return;
+        }
+
+        let current = &mut self.nodes[self.current];
+        let same_kind =
+            transaction.kind != EditKind::Other && transaction.kind == current.transaction.kind;
+        let continues = current.transaction.selection_after == transaction.selection_before;
+        let recent = transaction.kind == EditKind::Composition
+            || transaction
+                .last_edited_at
+                .duration_since(current.transaction.last_edited_at)
+                < GROUP_INTERVAL;
+        let is_leaf = current.parent.is_some() && current.children.is_empty();
+        if self.can_group && same_kind && is_leaf && continues && recent {
+            current.transaction.edits.extend(transaction.edits);
+            current.transaction.selection_after = transaction.selection_after;
+            current.transaction.last_edited_at = transaction.last_edited_at;
+            return;
+        }
+
+        let id = self.nodes.len();
+        self.nodes.push(Node {
+            parent: Some(self.current),
+            children: Vec::new(),
+            active_child: None,
+            transaction,
+            created_at: SystemTime::now(),
+        });
+        let parent = &mut self.nodes[self.current];
+        parent.children.push(id);
+        parent.active_child = Some(id);
+        self.current = id;
+        self.can_group = true;
+    }
+
+    /// Move to the parent state, returning the transaction to revert
+    pub fn undo(&mut self) -> Option<&Transaction> {
+        let id = self.current;
+        let parent = self.nodes[id].parent?;
+        self.nodes[parent].active_child = Some(id);
+        self.current = parent;
+        self.can_group = false;
+        Some(&self.nodes[id].transaction)
+    }
+
+    /// Move to the most recently visited child state, returning the transaction to reapply
+    pub fn redo(&mut self) -> Option<&Transaction> {
+        let child = self.nodes[self.current].active_child?;
+        self.current = child;
+        self.can_group = false;
+        Some(&self.nodes[child].transaction)
+    }
+
+    /// Move to any state, returning the transactions to revert and then reapply to get there
+    pub fn jump_to(
+        &mut self,
+        target: TransactionId,
+    ) -> Option<(Vec<&Transaction>, Vec<&Transaction>)> {
+        if target >= self.nodes.len() {
+            return None;
+        }
+        let (revert, apply) = self.path_to(target);
+        for &id in &apply {
+            if let Some(parent) = self.nodes[id].parent {
+                self.nodes[parent].active_child = Some(id);
+            }
+        }
+        self.current = target;
+        self.can_group = false;
+        Some((
+            revert
+                .iter()
+                .map(|&id| &self.nodes[id].transaction)
+                .collect(),
+            apply
+                .iter()
+                .map(|&id| &self.nodes[id].transaction)
+                .collect(),
+        ))
+    }
+
+    /// The states between the current one and `target`: those to revert going up to their
+    /// common ancestor, then those to reapply going down to the target
+    pub fn path_to(&self, target: TransactionId) -> (Vec<TransactionId>, Vec<TransactionId>) {
+        let ancestors = |mut id: TransactionId| {
+            let mut path = vec![id];
+            while let Some(parent) = self.nodes[id].parent {
+                path.push(parent);
+                id = parent;
+            }
+            path
+        };
+        let from = ancestors(self.current);
+        let to = ancestors(target);
+        let shared = to.iter().copied().collect::<HashSet<_>>();
+        let common = from
+            .iter()
+            .copied()
+            .find(|id| shared.contains(id))
+            .unwrap_or(0);
+
+        let revert = from.into_iter().take_while(|&id| id != common).collect();
+        let mut apply = to
+            .into_iter()
+            .take_while(|&id| id != common)
+            .collect::<Vec<_>>();
+        apply.reverse();
+        (revert, apply)
+    }
+
+    pub fn transaction(&self, id: TransactionId) -> Option<&Transaction> {
+        self.nodes.get(id).map(|node| &node.transaction)
+    }
+
+    pub fn current(&self) -> TransactionId {
+        self.current
+    }
+
+    /// Remember the current state as the one on disk. Later edits start a new state rather
+    /// than merging into this one.
+    pub fn mark_saved(&mut self) {
+        self.saved = Some(self.current);
+        self.can_group = false;
+    }
+
+    /// Keep the next transaction from merging into the current one
+    pub fn finish_group(&mut self) {
+        self.can_group = false;
+    }
+
+    /// Whether the current state is the one last saved
+    pub fn is_saved(&self) -> bool {
+        self.saved == Some(self.current)
+    }
+
+    /// List every state, each branch right after the state it was made from and indented
+    /// one level deeper
+    pub fn entries(&self) -> Vec<HistoryEntry> {
+        let mut entries = Vec::with_capacity(self.nodes.len());
+        let mut stack = vec![(0, 0)];
+        while let Some((id, depth)) = stack.pop() {
+            let node = &self.nodes[id];
+            entries.push(HistoryEntry {
+                id,
+                depth,
+                created_at: node.created_at,
+                summary: node.transaction.summary(),
+                is_current: id == self.current,
+            });
+            if let Some((first, branches)) = node.children.split_first() {
+                stack.push((*first, depth));
+                stack.extend(branches.iter().rev().map(|&child| (child, depth + 1)));
+            }
+        }
+        entries
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.nodes[self.current].parent.is_some()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.nodes[self.current].active_child.is_some()
+    }
+
+    /// Forget all states, making the current text the original
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(pos: usize, text: &str) -> Edit {
+        Edit::Insert {
+            pos: ByteOffset(pos),
+            text: text.to_string(),
+        }
+    }
+
+    fn remove(pos: usize, text: &str) -> Edit {
+        Edit::Remove {
+            pos: ByteOffset(pos),
+            text: text.to_string(),
+        }
+    }
+
+    /// Record one edit the way the editor does, moving the cursor from `before` to `after`
+    fn edit(history: &mut History, kind: EditKind, before: usize, after: usize, edit: Edit) {
+        history.start_transaction(&Selection::cursor(ByteOffset(before)), kind);
+        history.push(edit);
+        history.end_transaction(&Selection::cursor(ByteOffset(after)));
+    }
+
+    #[test]
+    fn test_typing_coalesces() {
+        let mut history = History::new();
+        edit(&mut history, EditKind::Typing, 0, 1, insert(0, "a"));
+        edit(&mut history, EditKind::Typing, 1, 2, insert(1, "b"));
+        edit(&mut history, EditKind::Typing, 2, 3, insert(2, "c"));
+        assert_eq!(history.current(), 1);
+        assert_eq!(history.transaction(1).unwrap().edits.len(), 3);
+
+        // Deleting is a new step, but consecutive deletions merge
+        edit(&mut history, EditKind::Deletion, 3, 2, remove(2, "c"));
+        edit(&mut history, EditKind::Deletion, 2, 1, remove(1, "b"));
+        assert_eq!(history.current(), 2);
+
+        // Typing that doesn't continue from the cursor is a new step
+        edit(&mut history, EditKind::Typing, 0, 1, insert(0, "x"));
+        assert_eq!(history.current(), 3);
+    }
+
+    #[test]
+    fn test_other_edits_never_coalesce() {
+        let mut history = History::new();
+        edit(&mut history, EditKind::Typing, 0, 1, insert(0, "a"));
+        edit(&mut history, EditKind::Other, 1, 6, insert(1, "paste"));
+        edit(&mut history, EditKind::Other, 6, 11, insert(6, "paste"));
+        edit(&mut history, EditKind::Typing, 11, 12, insert(11, "b"));
+        assert_eq!(history.current(), 4);
+
+        // Edits recorded without a transaction stand alone too
+        history.push(insert(12, "c"));
+        assert_eq!(history.current(), 5);
+    }
+
+    #[test]
+    fn test_composition_is_one_step() {
+        let mut history = History::new();
+        edit(&mut history, EditKind::Typing, 0, 1, insert(0, "a"));
+
+        // Each update replaces the marked text, and committing replaces it once more
+        edit(&mut history, EditKind::Composition, 1, 2, insert(1, "k"));
+        edit(&mut history, EditKind::Composition, 2, 1, remove(1, "k"));
+        edit(&mut history, EditKind::Composition, 1, 3, insert(1, "か"));
+        edit(&mut history, EditKind::Composition, 3, 1, remove(1, "か"));
+        edit(&mut history, EditKind::Composition, 1, 4, insert(1, "課"));
+        history.finish_group();
+        assert_eq!(history.current(), 2);
+        assert_eq!(history.transaction(2).unwrap().edits.len(), 5);
+
+        // The next composition starts its own step
+        edit(&mut history, EditKind::Composition, 4, 5, insert(4, "k"));
+        assert_eq!(history.current(), 3);
+        assert_eq!(history.undo().map(|t| t.edits.len()), Some(1));
+        assert_eq!(history.undo().map(|t| t.edits.len()), Some(5));
+    }
+
+    #[test]
+    fn test_no_coalescing_after_undo_or_save() {
+        let mut history = History::new();
+        edit(&mut history, EditKind::Typing, 0, 1, insert(0, "a"));
+        history.mark_saved();
+        edit(&mut history, EditKind::Typing, 1, 2, insert(1, "b"));
+        assert_eq!(history.current(), 2);
+
+        history.undo();
+        history.redo();
+        edit(&mut history, EditKind::Typing, 2, 3, insert(2, "c"));
+        assert_eq!(history.current(), 3);
+    }
+
+    #[test]
+    fn test_branching() {
+        let mut history = History::new();
+        edit(&mut history, EditKind::Other, 0, 1, insert(0, "a"));
+        edit(&mut history, EditKind::Other, 1, 2, insert(1, "b"));
+        assert_eq!(history.undo().map(|t| t.edits.len()), Some(1));
+        assert_eq!(history.current(), 1);
+
+        // Editing after an undo branches instead of discarding "b"
+        edit(&mut history, EditKind::Other, 1, 2, insert(1, "c"));
+        assert_eq!(history.current(), 3);
+        let depths = history
+            .entries()
+            .iter()
+            .map(|entry| (entry.id, entry.depth))
+            .collect::<Vec<_>>();
+        assert_eq!(depths, [(0, 0), (1, 0), (3, 1), (2, 0)]);
+
+        // Redo follows the newest branch
+        history.undo();
+        history.redo();
+        assert_eq!(history.current(), 3);
+
+        assert_eq!(history.path_to(2), (vec![3], vec![2]));
+        assert_eq!(history.path_to(0), (vec![3, 1], vec![]));
+        let (revert, apply) = history.jump_to(2).unwrap();
+        assert_eq!((revert.len(), apply.len()), (1, 1));
+        assert_eq!(history.current(), 2);
+
+        // Jumping makes the visited branch the one redo follows
+        history.undo();
+        history.redo();
+        assert_eq!(history.current(), 2);
+        assert!(history.jump_to(10).is_none());
+    }
+
+    #[test]
+    fn test_saved_state() {
+        let mut history = History::new();
+        assert!(history.is_saved());
+        edit(&mut history, EditKind::Other, 0, 1, insert(0, "a"));
+        assert!(!history.is_saved());
+        history.mark_saved();
+        assert!(history.is_saved());
+
+        edit(&mut history, EditKind::Other, 1, 2, insert(1, "b"));
+        assert!(!history.is_saved());
+        history.undo();
+        assert!(history.is_saved());
+        history.undo();
+        assert!(!history.is_saved());
+        history.redo();
+        assert!(history.is_saved());
+
+        // A branch made from the saved state is unsaved, and so is the state it replaced
+        edit(&mut history, EditKind::Other, 1, 2, insert(1, "c"));
+        assert!(!history.is_saved());
+        history.jump_to(2);
+        assert!(!history.is_saved());
+        history.jump_to(1);
+        assert!(history.is_saved());
+
+        history.clear();
+        assert!(history.is_saved());
+    }
+}
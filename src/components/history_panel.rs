@@ -0,0 +1,228 @@
+use crate::components::diff_view::LineDiff;
+use crate::components::history::{HistoryEntry, TransactionId};
+use crate::components::TextEditor;
+use gpui::{prelude::*, *};
+use std::ops::Range;
+use std::time::SystemTime;
+
+/// Lists every state in an editor's undo tree, previews how it differs from the current
+/// text and restores it on request
+pub struct HistoryPanel {
+    focus_handle: FocusHandle,
+    editor: Entity<TextEditor>,
+    /// Every state in the undo tree, listed when the buffer was at `version`
+    entries: Vec<HistoryEntry>,
+    version: Option<usize>,
+    /// The state being previewed, with how restoring it would change the current text
+    preview: Option<(TransactionId, LineDiff)>,
+    _editor_subscription: Subscription,
+}
+
+impl HistoryPanel {
+    pub fn new(editor: Entity<TextEditor>, cx: &mut Context<Self>) -> Self {
+        // The editor notifies on every cursor move, but the list only follows edits
+        let subscription = cx.observe(&editor, |this, _, cx| this.refresh(cx));
+        let mut panel = Self {
+            focus_handle: cx.focus_handle(),
+            editor,
+            entries: Vec::new(),
+            version: None,
+            preview: None,
+            _editor_subscription: subscription,
+        };
+        panel.refresh(cx);
+        panel
+    }
+
+    /// List the states again, and redo the previewed diff, if the buffer changed since
+    fn refresh(&mut self, cx: &mut Context<Self>) {
+        let buffer = self.editor.read(cx).buffer();
+        if self.version == Some(buffer.version()) {
+            return;
+        }
+        self.version = Some(buffer.version());
+        self.entries = buffer.history().entries();
+        // Opening another file replaces the history the preview pointed into
+        let selected = self
+            .preview
+            .take()
+            .map(|(id, _)| id)
+            .filter(|id| self.entries.iter().any(|entry| entry.id == *id));
+        if let Some(id) = selected {
+            self.preview_state(id, cx);
+        }
+        cx.notify();
+    }
+
+    fn preview_state(&mut self, id: TransactionId, cx: &App) {
+        let buffer = self.editor.read(cx).buffer();
+        let diff = LineDiff::new(&buffer.to_string(), &buffer.text_at(id).to_string());
+        self.preview = Some((id, diff));
+    }
+
+    fn select(&mut self, id: TransactionId, cx: &mut Context<Self>) {
+        if self.selected() != Some(id) {
+            self.preview_state(id, cx);
+            cx.notify();
+        }
+    }
+
+    fn selected(&self) -> Option<TransactionId> {
+        self.preview.as_ref().map(|(id, _)| *id)
+    }
+
+    fn restore(&mut self, id: TransactionId, cx: &mut Context<Self>) {
+        self.preview = None;
+        self.editor
+            .update(cx, |editor, cx| editor.jump_to_history(id, cx));
+        cx.notify();
+    }
+
+    fn render_entry(&self, ix: usize, cx: &mut Context<Self>) -> Div {
+        let entry = &self.entries[ix];
+        let id = entry.id;
+        let is_selected = self.selected() == Some(id);
+        let marker = if entry.is_current { "●" } else { "○" };
+
+        div()
+            .flex()
+            .items_center()
+            .gap_2()
+            .px_2()
+            .py_1()
+            .pl(px(8. + entry.depth as f32 * 16.))
+            .cursor_pointer()
+            .when(is_selected, |div| div.bg(rgb(0xe0ecff)))
+            .when(!is_selected, |div| {
+                div.hover(|style| style.bg(rgb(0xf0f0f0)))
+            })
+            // A click previews the state, a double click restores it
+            .on_mouse_down(
+                MouseButton::Left,
+                cx.listener(move |this, event: &MouseDownEvent, _window, cx| {
+                    if event.click_count >= 2 {
+                        this.restore(id, cx);
+                    } else {
+                        this.select(id, cx);
+                    }
+                }),
+            )
+            .child(div().text_xs().text_color(rgb(0x0066ff)).child(marker))
+            .child(
+                div()
+                    .flex_1()
+                    .overflow_hidden()
+                    .text_sm()
+                    .text_color(rgb(0x333333))
+                    .when(entry.is_current, |div| div.font_weight(FontWeight::BOLD))
+                    .child(entry.summary.clone()),
+            )
+            .child(
+                div()
+                    .text_xs()
+                    .text_color(rgb(0x999999))
+                    .child(format_age(entry.created_at)),
+            )
+    }
+
+    /// Show the lines that restoring a state would remove and add
+    fn render_diff(&self, id: TransactionId, diff: &LineDiff, cx: &mut Context<Self>) -> Div {
+        div()
+            .flex()
+            .flex_col()
+            .border_t_1()
+            .border_color(rgb(0xe5e5e5))
+            .child(
+                div()
+                    .flex()
+                    .items_center()
+                    .justify_between()
+                    .p_2()
+                    .child(
+                        div()
+                            .text_sm()
+                            .font_weight(FontWeight::SEMIBOLD)
+                            .text_color(rgb(0x333333))
+                            .child("Changes if restored"),
+                    )
+                    .child(
+                        div()
+                            .px_2()
+                            .py_1()
+                            .rounded_md()
+                            .bg(rgb(0x0066ff))
+                            .text_sm()
+                            .text_color(white())
+                            .cursor_pointer()
+                            .on_mouse_down(
+                                MouseButton::Left,
+                                cx.listener(move |this, _event: &MouseDownEvent, _window, cx| {
+                                    this.restore(id, cx);
+                                }),
+                            )
+                            .child("Restore"),
+                    ),
+            )
+            .child(diff.render())
+    }
+}
+
+/// Describe how long ago a time was, like "5m ago"
+fn format_age(time: SystemTime) -> String {
+    let seconds = time.elapsed().map_or(0, |age| age.as_secs());
+    match seconds {
+        0..60 => format!("{}s ago", seconds),
+        60..3600 => format!("{}m ago", seconds / 60),
+        3600..86400 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    }
+}
+
+impl Focusable for HistoryPanel {
+    fn focus_handle(&self, _cx: &App) -> FocusHandle {
+        self.focus_handle.clone()
+    }
+}
+
+impl Render for HistoryPanel {
+    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        div()
+            .flex()
+            .flex_col()
+            .size_full()
+            .bg(white())
+            .track_focus(&self.focus_handle)
+            .child(
+                div()
+                    .p_2()
+                    .border_b_1()
+                    .border_color(rgb(0xe5e5e5))
+                    .bg(rgb(0xf9f9f9))
+                    .text_sm()
+                    .font_weight(FontWeight::SEMIBOLD)
+                    .text_color(rgb(0x333333))
+                    .child("History"),
+            )
+            .child(
+                uniform_list(
+                    "history-entries",
+                    self.entries.len(),
+                    cx.processor(|this, range: Range<usize>, _window, cx| {
+                        range
+                            .map(|ix| this.render_entry(ix, cx))
+                            .collect::<Vec<_>>()
+                    }),
+                )
+                .flex_1(),
+            )
+            .when_some(self.preview.as_ref(), |panel, (id, diff)| {
+                panel.child(
+                    div()
+                        .id("history-diff")
+                        .max_h(relative(0.5))
+                        .overflow_y_scroll()
+                        .child(self.render_diff(*id, diff, cx)),
+                )
+            })
+    }
+}
@@ -0,0 +1,209 @@
+use crate::components::history::History;
+use crate::components::save;
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Histories larger than this are not kept
+const MAX_FILE_BYTES: u64 = 4 * 1024 * 1024;
+/// Once a project's histories take more space than this the oldest are removed
+const MAX_PROJECT_BYTES: u64 = 64 * 1024 * 1024;
+/// Histories not written for this long are removed
+const MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// A file's undo history along with the text it leads to
+#[derive(Serialize, Deserialize)]
+struct StoredHistory {
+    path: PathBuf,
+    /// Hash of the text in the history's current state
+    content_hash: String,
+    history: History,
+}
+
+/// Keeps undo histories in the user's cache directory so they outlive the session. Each
+/// project gets its own directory and each file its own entry, keyed by canonical path.
+pub struct HistoryStore {
+    dir: PathBuf,
+}
+
+impl HistoryStore {
+    /// Open the store for a project, removing stale entries. Returns None when there's no
+    /// cache directory to write to.
+    pub fn new(project_root: &Path) -> Option<Self> {
+        let root = project_root.canonicalize().ok()?;
+        let dir = dirs::cache_dir()?
+            .join("poem")
+            .join("undo")
+            .join(hash_path(&root));
+        Self::in_dir(dir).ok()
+    }
+
+    fn in_dir(dir: PathBuf) -> std::io::Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        let store = Self { dir };
+        store.prune();
+        Ok(store)
+    }
+
+    /// Write a file's history, to be restored if the file is reopened with the same text
+    pub fn save(&self, path: &Path, text: &Rope, history: &History) -> std::io::Result<()> {
+        let path = path.canonicalize()?;
+        let stored = StoredHistory {
+            content_hash: hash_text(text),
+            path: path.clone(),
+            history: history.clone(),
+        };
+        let json = serde_json::to_vec(&stored)?;
+        let entry_path = self.entry_path(&path);
+        if json.len() as u64 > MAX_FILE_BYTES {
+            // An older, smaller history mustn't be restored in its place
+            return match std::fs::remove_file(&entry_path) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+                _ => Ok(()),
+            };
+        }
+        save::write_atomic(&entry_path, &json, false).map_err(std::io::Error::other)
+    }
+
+    /// Read a file's history if it was saved for exactly this text
+    pub fn load(&self, path: &Path, text: &Rope) -> Option<History> {
+        let path = path.canonicalize().ok()?;
+        let json = std::fs::read(self.entry_path(&path)).ok()?;
+        let stored: StoredHistory = serde_json::from_slice(&json).ok()?;
+        (stored.path == path && stored.content_hash == hash_text(text)).then_some(stored.history)
+    }
+
+    fn entry_path(&self, path: &Path) -> PathBuf {
+        self.dir.join(format!("{}.json", hash_path(path)))
+    }
+
+    /// Remove entries older than the age limit, then the oldest until the size limit is met
+    fn prune(&self) {
+        let Ok(read_dir) = std::fs::read_dir(&self.dir) else {
+            return;
+        };
+        let mut entries = read_dir
+            .flatten()
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                Some((entry.path(), metadata.modified().ok()?, metadata.len()))
+            })
+            .collect::<Vec<_>>();
+
+        // Newest first, so everything past the budget is the oldest
+        entries.sort_by(|a, b| b.1.cmp(&a.1));
+        let now = SystemTime::now();
+        let mut total = 0;
+        for (path, modified, len) in entries {
+            total += len;
+            let age = now.duration_since(modified).unwrap_or_default();
+            if age > MAX_AGE || total > MAX_PROJECT_BYTES {
+                std::fs::remove_file(path).ok();
+            }
+        }
+    }
+}
+
+fn hash_path(path: &Path) -> String {
+    format!("{:x}", Sha256::digest(path.as_os_str().as_encoded_bytes()))
+}
+
+fn hash_text(text: &Rope) -> String {
+    let mut hasher = Sha256::new();
+    for chunk in text.chunks() {
+        hasher.update(chunk.as_bytes());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::history::{Edit, EditKind};
+    use crate::components::test_support::TempDir;
+    use crate::components::{ByteOffset, Selection};
+    use std::fs::File;
+
+    fn history_with_insert(text: &str) -> History {
+        let mut history = History::new();
+        history.start_transaction(&Selection::cursor(ByteOffset(0)), EditKind::Other);
+        history.push(Edit::Insert {
+            pos: ByteOffset(0),
+            text: text.to_string(),
+        });
+        history.end_transaction(&Selection::cursor(ByteOffset(text.len())));
+        history
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = TempDir::new("history-store");
+        let file = dir.join("notes.txt");
+        std::fs::write(&file, "hello").unwrap();
+        let store = HistoryStore::in_dir(dir.join("store")).unwrap();
+
+        let text = Rope::from_str("hello");
+        store
+            .save(&file, &text, &history_with_insert("hello"))
+            .unwrap();
+        let history = store.load(&file, &text).unwrap();
+        assert_eq!(history.current(), 1);
+
+        // Text changed outside the editor doesn't match the history
+        assert!(store.load(&file, &Rope::from_str("hello!")).is_none());
+        // Nor does another file
+        let other = dir.join("other.txt");
+        std::fs::write(&other, "hello").unwrap();
+        assert!(store.load(&other, &text).is_none());
+    }
+
+    #[test]
+    fn test_oversized_history_replaces_entry() {
+        let dir = TempDir::new("history-store-oversized");
+        let file = dir.join("big.txt");
+        std::fs::write(&file, "").unwrap();
+        let store = HistoryStore::in_dir(dir.join("store")).unwrap();
+
+        let text = Rope::new();
+        store.save(&file, &text, &History::new()).unwrap();
+        assert!(store.load(&file, &text).is_some());
+
+        let big = "x".repeat(MAX_FILE_BYTES as usize);
+        store
+            .save(&file, &text, &history_with_insert(&big))
+            .unwrap();
+        assert!(store.load(&file, &text).is_none());
+        assert_eq!(std::fs::read_dir(&store.dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_prune() {
+        let dir = TempDir::new("history-store-prune");
+        let store_dir = dir.join("store");
+        std::fs::create_dir(&store_dir).unwrap();
+        let now = SystemTime::now();
+        let entry = |name: &str, age: Duration, len: u64| {
+            let file = File::create(store_dir.join(name)).unwrap();
+            file.set_len(len).unwrap();
+            file.set_modified(now - age).unwrap();
+        };
+        let day = Duration::from_secs(24 * 60 * 60);
+        let third = MAX_PROJECT_BYTES / 3;
+        entry("stale.json", MAX_AGE + day, 1);
+        entry("newest.json", Duration::ZERO, third);
+        entry("newer.json", day, third);
+        entry("older.json", 2 * day, third);
+        entry("oldest.json", 3 * day, third);
+
+        // The stale entry goes, then the oldest until the rest fit
+        HistoryStore::in_dir(store_dir.clone()).unwrap();
+        let mut kept = std::fs::read_dir(&store_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        kept.sort();
+        assert_eq!(kept, ["newer.json", "newest.json", "older.json"]);
+    }
+}
@@ -0,0 +1,295 @@
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockWriteGuard};
+
+/// Files in each directory whose rules apply to it and everything below it. Later ones
+/// take precedence.
+const IGNORE_FILE_NAMES: &[&str] = &[".gitignore", ".ignore"];
+
+/// Whether a path holds ignore rules: a directory's ignore file, or `.git/info/exclude`
+pub fn is_ignore_file(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| IGNORE_FILE_NAMES.iter().any(|file| name == *file))
+        || path.ends_with(Path::new(".git").join("info").join("exclude"))
+}
+
+/// Rules that apply across the whole worktree, and each directory's own rules as they're
+/// read. Files are only read again once the watcher reports them changed.
+pub struct WorktreeIgnores {
+    root: PathBuf,
+    /// Globs configured in poem, which always hide a match
+    excludes: Gitignore,
+    /// The repository's `.git/info/exclude`
+    repo_exclude: RwLock<Arc<Gitignore>>,
+    /// The user's global gitignore
+    global: Gitignore,
+    dirs: Mutex<DirRules>,
+}
+
+/// Each directory's rules by path, None for a directory without ignore files
+#[derive(Default)]
+struct DirRules {
+    rules: HashMap<PathBuf, Option<Arc<Gitignore>>>,
+    /// Bumped on every invalidation, so rules read before one aren't cached after it
+    generation: usize,
+}
+
+impl WorktreeIgnores {
+    pub fn new(root: &Path, exclude_globs: &[String]) -> Self {
+        let mut excludes = GitignoreBuilder::new(root);
+        for glob in exclude_globs {
+            excludes.add_line(None, glob).ok();
+        }
+
+        Self {
+            root: root.to_path_buf(),
+            excludes: excludes.build().unwrap_or_else(|_| Gitignore::empty()),
+            repo_exclude: RwLock::new(Arc::new(read_repo_exclude(root))),
+            global: Gitignore::global().0,
+            dirs: Mutex::default(),
+        }
+    }
+
+    /// Forget rules read from a changed path: an ignore file's directory, or any directory
+    /// at or below the path. Returns whether `.git/info/exclude` changed, which can
+    /// affect every entry.
+    pub fn invalidate(&self, path: &Path) -> bool {
+        let rules_dir = path.parent().filter(|_| is_ignore_file(path));
+        let mut dirs = self.dir_rules();
+        dirs.generation += 1;
+        dirs.rules
+            .retain(|dir, _| !dir.starts_with(path) && Some(dir.as_path()) != rules_dir);
+        drop(dirs);
+
+        let exclude_changed = repo_exclude_path(&self.root).starts_with(path);
+        if exclude_changed {
+            *self.write_repo_exclude() = Arc::new(read_repo_exclude(&self.root));
+        }
+        exclude_changed
+    }
+
+    /// Forget every directory's rules and read `.git/info/exclude` again
+    pub fn invalidate_all(&self) {
+        let mut dirs = self.dir_rules();
+        dirs.generation += 1;
+        dirs.rules.clear();
+        drop(dirs);
+        *self.write_repo_exclude() = Arc::new(read_repo_exclude(&self.root));
+    }
+
+    /// A directory's own rules, read from its ignore files the first time they're needed
+    fn rules_for(&self, dir: &Path) -> Option<Arc<Gitignore>> {
+        let generation = {
+            let dirs = self.dir_rules();
+            if let Some(rules) = dirs.rules.get(dir) {
+                return rules.clone();
+            }
+            dirs.generation
+        };
+        let rules = read_dir_rules(dir);
+        let mut dirs = self.dir_rules();
+        if dirs.generation == generation {
+            dirs.rules.insert(dir.to_path_buf(), rules.clone());
+        }
+        rules
+    }
+
+    fn dir_rules(&self) -> MutexGuard<'_, DirRules> {
+        self.dirs.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn repo_exclude(&self) -> Arc<Gitignore> {
+        self.repo_exclude
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone()
+    }
+
+    fn write_repo_exclude(&self) -> RwLockWriteGuard<'_, Arc<Gitignore>> {
+        self.repo_exclude
+            .write()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+fn repo_exclude_path(root: &Path) -> PathBuf {
+    root.join(".git").join("info").join("exclude")
+}
+
+fn read_repo_exclude(root: &Path) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(root);
+    builder.add(repo_exclude_path(root));
+    builder.build().unwrap_or_else(|_| Gitignore::empty())
+}
+
+/// Build the rules from a directory's ignore files, if it has any
+fn read_dir_rules(dir: &Path) -> Option<Arc<Gitignore>> {
+    let files = IGNORE_FILE_NAMES
+        .iter()
+        .map(|name| dir.join(name))
+        .filter(|path| path.is_file())
+        .collect::<Vec<PathBuf>>();
+    if files.is_empty() {
+        return None;
+    }
+    let mut builder = GitignoreBuilder::new(dir);
+    for file in files {
+        builder.add(file);
+    }
+    builder.build().ok().map(Arc::new)
+}
+
+/// The ignore rules in effect for one directory: those of the directory and its ancestors,
+/// plus the worktree-wide ones
+#[derive(Clone)]
+pub struct IgnoreStack {
+    /// Each directory's rules, innermost last
+    dirs: Vec<Arc<Gitignore>>,
+    /// `.git/info/exclude` as it was when the stack was made
+    repo_exclude: Arc<Gitignore>,
+    worktree: Arc<WorktreeIgnores>,
+}
+
+impl IgnoreStack {
+    /// Collect the rules for `dir` from the root down to it
+    pub fn for_dir(worktree: Arc<WorktreeIgnores>, root: &Path, dir: &Path) -> Self {
+        let mut stack = Self {
+            dirs: Vec::new(),
+            repo_exclude: worktree.repo_exclude(),
+            worktree,
+        };
+        let relative = dir.strip_prefix(root).unwrap_or(Path::new(""));
+        let mut current = root.to_path_buf();
+        stack.push_dir(&current);
+        for component in relative.components() {
+            current.push(component);
+            stack.push_dir(&current);
+        }
+        stack
+    }
+
+    /// The rules for a child directory of the one this stack is for
+    pub fn child(&self, dir: &Path) -> Self {
+        let mut stack = self.clone();
+        stack.push_dir(dir);
+        stack
+    }
+
+    fn push_dir(&mut self, dir: &Path) {
+        if let Some(rules) = self.worktree.rules_for(dir) {
+            self.dirs.push(rules);
+        }
+    }
+
+    /// Whether a path in this stack's directory is ignored. The nearest rule that matches
+    /// decides, so a nested `!pattern` can bring back something ignored further up.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if path.file_name().is_some_and(|name| name == ".git") {
+            return true;
+        }
+        if self.worktree.excludes.matched(path, is_dir).is_ignore() {
+            return true;
+        }
+        let rules = self
+            .dirs
+            .iter()
+            .rev()
+            .map(|gitignore| &**gitignore)
+            .chain([&*self.repo_exclude, &self.worktree.global]);
+        for gitignore in rules {
+            match gitignore.matched(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::test_support::TempDir;
+
+    fn write(path: PathBuf, contents: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    fn is_ignored(ignores: &Arc<WorktreeIgnores>, root: &Path, path: &str, is_dir: bool) -> bool {
+        let path = root.join(path);
+        IgnoreStack::for_dir(ignores.clone(), root, path.parent().unwrap())
+            .is_ignored(&path, is_dir)
+    }
+
+    #[test]
+    fn test_precedence() {
+        let root = TempDir::new("ignore-precedence");
+        write(root.join(".gitignore"), "*.log\n!keep.log\nbuild/\n");
+        write(root.join("sub").join(".gitignore"), "!*.log\nsecret.txt\n");
+        write(
+            root.join(".git").join("info").join("exclude"),
+            "local.txt\n",
+        );
+        let ignores = Arc::new(WorktreeIgnores::new(&root, &["*.tmp".to_string()]));
+
+        assert!(is_ignored(&ignores, &root, "debug.log", false));
+        // A negation brings a file back
+        assert!(!is_ignored(&ignores, &root, "keep.log", false));
+        // A pattern ending in a slash only matches directories
+        assert!(is_ignored(&ignores, &root, "build", true));
+        assert!(!is_ignored(&ignores, &root, "build", false));
+        // The nearer ignore file wins over its parent's
+        assert!(!is_ignored(&ignores, &root, "sub/debug.log", false));
+        assert!(is_ignored(&ignores, &root, "sub/secret.txt", false));
+        assert!(!is_ignored(&ignores, &root, "secret.txt", false));
+        assert!(is_ignored(&ignores, &root, "local.txt", false));
+        assert!(!is_ignored(&ignores, &root, "notes.txt", false));
+    }
+
+    #[test]
+    fn test_excludes_and_git_always_hide() {
+        let root = TempDir::new("ignore-excludes");
+        write(root.join(".gitignore"), "!*.tmp\n!.git\n");
+        write(root.join("sub").join(".gitignore"), "!*.tmp\n");
+        let ignores = Arc::new(WorktreeIgnores::new(&root, &["*.tmp".to_string()]));
+
+        // Configured globs can't be negated by a project's ignore files
+        assert!(is_ignored(&ignores, &root, "a.tmp", false));
+        assert!(is_ignored(&ignores, &root, "sub/a.tmp", false));
+        assert!(is_ignored(&ignores, &root, ".git", true));
+        assert!(is_ignored(&ignores, &root, "sub/.git", false));
+    }
+
+    #[test]
+    fn test_invalidate() {
+        let root = TempDir::new("ignore-invalidate");
+        write(root.join("sub").join(".gitignore"), "a.txt\n");
+        let ignores = Arc::new(WorktreeIgnores::new(&root, &[]));
+        assert!(is_ignored(&ignores, &root, "sub/a.txt", false));
+
+        // Rules are read once, until the file is reported changed
+        write(root.join("sub").join(".gitignore"), "b.txt\n");
+        assert!(is_ignored(&ignores, &root, "sub/a.txt", false));
+        assert!(!ignores.invalidate(&root.join("sub").join(".gitignore")));
+        assert!(!is_ignored(&ignores, &root, "sub/a.txt", false));
+        assert!(is_ignored(&ignores, &root, "sub/b.txt", false));
+
+        // Replacing a whole directory forgets the rules inside it
+        std::fs::remove_dir_all(root.join("sub")).unwrap();
+        write(root.join("sub").join(".gitignore"), "c.txt\n");
+        ignores.invalidate(&root.join("sub"));
+        assert!(is_ignored(&ignores, &root, "sub/c.txt", false));
+
+        // `.git/info/exclude` is read again when it changes
+        let exclude = root.join(".git").join("info").join("exclude");
+        write(exclude.clone(), "d.txt\n");
+        assert!(!is_ignored(&ignores, &root, "d.txt", false));
+        assert!(ignores.invalidate(&exclude));
+        assert!(is_ignored(&ignores, &root, "d.txt", false));
+        assert!(is_ignore_file(&exclude));
+    }
+}
@@ -0,0 +1,138 @@
+use std::borrow::Cow;
+
+/// The character sequence a file ends its lines with. Buffers always hold `\n` and convert
+/// when reading and writing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+    Cr,
+}
+
+impl LineEnding {
+    /// Find the line ending used most in some text, defaulting to LF
+    pub fn detect(text: &str) -> Self {
+        let (mut lf, mut crlf, mut cr) = (0, 0, 0);
+        let mut bytes = text.bytes().peekable();
+        while let Some(byte) = bytes.next() {
+            match byte {
+                b'\r' if bytes.peek() == Some(&b'\n') => {
+                    bytes.next();
+                    crlf += 1;
+                }
+                b'\r' => cr += 1,
+                b'\n' => lf += 1,
+                _ => {}
+            }
+        }
+
+        if crlf > lf && crlf >= cr {
+            Self::Crlf
+        } else if cr > lf && cr > crlf {
+            Self::Cr
+        } else {
+            Self::Lf
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::Crlf => "\r\n",
+            Self::Cr => "\r",
+        }
+    }
+
+    /// Short name for the status area
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Lf => "LF",
+            Self::Crlf => "CRLF",
+            Self::Cr => "CR",
+        }
+    }
+
+    /// The ending to convert to next when cycling through them
+    pub fn next(&self) -> Self {
+        match self {
+            Self::Lf => Self::Crlf,
+            Self::Crlf => Self::Cr,
+            Self::Cr => Self::Lf,
+        }
+    }
+
+    /// Write `\n`-separated text with this ending
+    pub fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match self {
+            Self::Lf => Cow::Borrowed(text),
+            _ => Cow::Owned(text.replace('\n', self.as_str())),
+        }
+    }
+}
+
+/// Convert CRLF and lone CR line endings to `\n`
+pub fn normalize(text: &str) -> Cow<'_, str> {
+    if text.contains('\r') {
+        Cow::Owned(text.replace("\r\n", "\n").replace('\r', "\n"))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(LineEnding::detect(""), LineEnding::Lf);
+        assert_eq!(LineEnding::detect("no breaks"), LineEnding::Lf);
+        assert_eq!(LineEnding::detect("a\nb\n"), LineEnding::Lf);
+        assert_eq!(LineEnding::detect("a\r\nb\r\n"), LineEnding::Crlf);
+        assert_eq!(LineEnding::detect("a\rb\r"), LineEnding::Cr);
+
+        // Mixed endings go with the majority, and ties with LF
+        assert_eq!(LineEnding::detect("a\r\nb\r\nc\n"), LineEnding::Crlf);
+        assert_eq!(LineEnding::detect("a\r\nb\nc\n"), LineEnding::Lf);
+        assert_eq!(LineEnding::detect("a\rb\rc\r\n"), LineEnding::Cr);
+        assert_eq!(LineEnding::detect("a\r\nb\n"), LineEnding::Lf);
+        assert_eq!(LineEnding::detect("a\r\nb\r"), LineEnding::Crlf);
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert!(matches!(normalize("a\nb"), Cow::Borrowed("a\nb")));
+        assert_eq!(normalize("a\r\nb\rc\n"), "a\nb\nc\n");
+        // A CR before a CRLF is a line of its own
+        assert_eq!(normalize("a\r\r\nb"), "a\n\nb");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for text in [
+            "one\ntwo\n\nthree",
+            "one\r\ntwo\r\n\r\nthree",
+            "one\rtwo\r\rthree",
+        ] {
+            let line_ending = LineEnding::detect(text);
+            assert_eq!(line_ending.apply(&normalize(text)), text);
+        }
+
+        // Converting changes every line, whatever it ended with
+        assert_eq!(LineEnding::Crlf.apply(&normalize("a\rb\nc")), "a\r\nb\r\nc");
+        assert_eq!(LineEnding::Cr.apply("a\nb"), "a\rb");
+    }
+
+    #[test]
+    fn test_next_cycles_through_all() {
+        let mut line_ending = LineEnding::Lf;
+        let mut seen = Vec::new();
+        for _ in 0..3 {
+            seen.push(line_ending.label());
+            line_ending = line_ending.next();
+        }
+        assert_eq!(line_ending, LineEnding::Lf);
+        assert_eq!(seen, ["LF", "CRLF", "CR"]);
+    }
+}
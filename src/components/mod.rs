@@ -1,9 +1,36 @@
 mod buffer;
+mod coordinates;
+mod diff_view;
+mod disk_state;
+mod editor_element;
+mod encoding;
+mod entry_tree;
+mod file_ops;
+mod git_status;
+mod history;
+mod history_panel;
+mod history_store;
+mod ignore_rules;
+mod line_ending;
+mod movement;
+mod save;
+mod selection;
+mod settings;
+#[cfg(test)]
+mod test_support;
 pub mod text_editor;
 mod text_input;
+mod trash;
 pub mod worktree;
 
 pub use buffer::Buffer;
-pub use text_editor::TextEditor;
-pub use text_input::TextInput;
+pub use coordinates::{ByteOffset, CharOffset, OffsetUtf16, Point, PointUtf16};
+pub use history_panel::HistoryPanel;
+pub use history_store::HistoryStore;
+pub use line_ending::LineEnding;
+pub use save::SaveError;
+pub use selection::{Selection, SelectionGoal};
+pub use settings::Settings;
+pub use text_editor::{LineNumbers, TextEditor};
+pub use text_input::{TextInput, TextInputEvent};
 pub use worktree::Worktree;
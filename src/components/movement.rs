@@ -0,0 +1,561 @@
+use crate::components::{Buffer, ByteOffset, CharOffset, Point, SelectionGoal};
+use std::ops::Range;
+use unicode_segmentation::{GraphemeCursor, GraphemeIncomplete, UnicodeSegmentation};
+
+/// Number of columns a tab advances to, used when computing display columns
+const TAB_SIZE: u32 = 4;
+
+/// Find the grapheme cluster boundary before an offset, feeding the rope's chunks to the
+/// segmenter so that clusters spanning chunk edges are handled without copying the text
+pub fn prev_grapheme_boundary(buffer: &Buffer, offset: ByteOffset) -> ByteOffset {
+    let rope = buffer.rope();
+    let (mut chunk, mut chunk_start, _, _) = rope.chunk_at_byte(offset.0);
+    let mut cursor = GraphemeCursor::new(offset.0, rope.len_bytes(), true);
+
+    loop {
+        match cursor.prev_boundary(chunk, chunk_start) {
+            Ok(None) => return ByteOffset(0),
+            Ok(Some(boundary)) => return ByteOffset(boundary),
+            Err(GraphemeIncomplete::PrevChunk) => {
+                (chunk, chunk_start, _, _) = rope.chunk_at_byte(chunk_start - 1);
+            }
+            Err(GraphemeIncomplete::PreContext(end)) => {
+                let (context, context_start, _, _) = rope.chunk_at_byte(end - 1);
+                cursor.provide_context(context, context_start);
+            }
+            Err(GraphemeIncomplete::NextChunk) => {
+                chunk_start += chunk.len();
+                (chunk, _, _, _) = rope.chunk_at_byte(chunk_start);
+            }
+            // The chunks given always hold the cursor, but a wrong boundary beats a panic
+            Err(GraphemeIncomplete::InvalidOffset) => {
+                let offset = buffer.byte_to_char(offset).0.saturating_sub(1);
+                return buffer.char_to_byte(CharOffset(offset));
+            }
+        }
+    }
+}
+
+/// Find the grapheme cluster boundary after an offset
+pub fn next_grapheme_boundary(buffer: &Buffer, offset: ByteOffset) -> ByteOffset {
+    let rope = buffer.rope();
+    let (mut chunk, mut chunk_start, _, _) = rope.chunk_at_byte(offset.0);
+    let mut cursor = GraphemeCursor::new(offset.0, rope.len_bytes(), true);
+
+    loop {
+        match cursor.next_boundary(chunk, chunk_start) {
+            Ok(None) => return buffer.len_bytes(),
+            Ok(Some(boundary)) => return ByteOffset(boundary),
+            Err(GraphemeIncomplete::NextChunk) => {
+                chunk_start += chunk.len();
+                (chunk, _, _, _) = rope.chunk_at_byte(chunk_start);
+            }
+            Err(GraphemeIncomplete::PreContext(end)) => {
+                let (context, context_start, _, _) = rope.chunk_at_byte(end - 1);
+                cursor.provide_context(context, context_start);
+            }
+            Err(GraphemeIncomplete::PrevChunk) => {
+                (chunk, chunk_start, _, _) = rope.chunk_at_byte(chunk_start - 1);
+            }
+            Err(GraphemeIncomplete::InvalidOffset) => {
+                let offset = (buffer.byte_to_char(offset).0 + 1).min(buffer.len_chars().0);
+                return buffer.char_to_byte(CharOffset(offset));
+            }
+        }
+    }
+}
+
+/// Find the start of the word before an offset. At the start of a row this moves to the
+/// end of the previous row, mirroring how the line break is skipped when moving by chars.
+pub fn prev_word_start(buffer: &Buffer, offset: ByteOffset, subword: bool) -> ByteOffset {
+    let point = buffer.offset_to_point(offset);
+    if point.column == 0 {
+        return prev_grapheme_boundary(buffer, offset);
+    }
+
+    let (start, text) = word_context(buffer, offset);
+    let column = offset - start;
+    let word_start = word_ranges(&text, subword)
+        .into_iter()
+        .rev()
+        .find(|range| range.start < column)
+        .map_or(0, |range| range.start);
+    start + word_start
+}
+
+/// Find the end of the word after an offset. At the end of a row this moves to the start
+/// of the next row.
+pub fn next_word_end(buffer: &Buffer, offset: ByteOffset, subword: bool) -> ByteOffset {
+    let point = buffer.offset_to_point(offset);
+    if point.column >= buffer.line_len(point.row) {
+        return next_grapheme_boundary(buffer, offset);
+    }
+
+    let (start, text) = word_context(buffer, offset);
+    let column = offset - start;
+    let word_end = word_ranges(&text, subword)
+        .into_iter()
+        .find(|range| range.end > column)
+        .map_or(text.len(), |range| range.end);
+    start + word_end
+}
+
+/// Find the word-boundary segment containing an offset, as selected by a double-click.
+/// Runs of whitespace and punctuation count as segments of their own.
+pub fn surrounding_word(buffer: &Buffer, offset: ByteOffset) -> Range<ByteOffset> {
+    let (start, text) = word_context(buffer, offset);
+    let column = offset - start;
+
+    let mut segments = text
+        .split_word_bound_indices()
+        .map(|(start, word)| start..start + word.len());
+    let segment = if column < text.len() {
+        segments.find(|range| range.contains(&column))
+    } else {
+        segments.last()
+    }
+    .unwrap_or(column..column);
+
+    start + segment.start..start + segment.end
+}
+
+/// The part of a row that word boundaries around an offset depend on, with where it starts.
+/// It reaches out from the offset one char at a time, walking the rope's chunks, until it
+/// has passed a word and come to a char that words never join across. A long row is only
+/// copied as far as the words beside the offset.
+fn word_context(buffer: &Buffer, offset: ByteOffset) -> (ByteOffset, String) {
+    let row = buffer.offset_to_point(offset).row;
+    let row_start = buffer.point_to_offset(Point::new(row, 0));
+    let row_end = buffer.point_to_offset(Point::new(row, buffer.line_len(row)));
+    let char_offset = buffer.byte_to_char(offset).0;
+
+    let mut start = offset;
+    let mut chars = buffer.rope().chars_at(char_offset);
+    let mut seen_word = false;
+    while start > row_start {
+        let Some(c) = chars.prev() else {
+            break;
+        };
+        start -= c.len_utf8();
+        if seen_word && breaks_words(c) {
+            // A run of spaces can join a combining mark after it, so take in the whole run
+            while start > row_start {
+                match chars.prev() {
+                    Some(c) if c.is_whitespace() => start -= c.len_utf8(),
+                    _ => break,
+                }
+            }
+            break;
+        }
+        seen_word |= is_word_char(c);
+    }
+
+    let mut end = offset;
+    let mut chars = buffer.rope().chars_at(char_offset);
+    let mut seen_word = false;
+    while end < row_end {
+        let Some(c) = chars.next() else {
+            break;
+        };
+        end += c.len_utf8();
+        if seen_word && breaks_words(c) {
+            break;
+        }
+        seen_word |= is_word_char(c);
+    }
+
+    (start, buffer.slice(start..end).to_string())
+}
+
+/// Whether a char is part of a word, even when words are split into subwords
+fn is_word_char(c: char) -> bool {
+    !c.is_whitespace() && c != '_' && c != '-'
+}
+
+/// Whether a char always has a word boundary on both sides: whitespace, or punctuation
+/// that Unicode doesn't let join the letters or digits around it, as `.` and `'` can
+fn breaks_words(c: char) -> bool {
+    c.is_whitespace()
+        || (c.is_ascii_punctuation() && !matches!(c, '.' | ':' | ',' | ';' | '\'' | '"' | '_'))
+}
+
+/// Move up by a number of rows, aiming for the goal column. Moving up from the first row
+/// goes to the start of the buffer.
+pub fn up(
+    buffer: &Buffer,
+    offset: ByteOffset,
+    goal: SelectionGoal,
+    rows: u32,
+) -> (ByteOffset, SelectionGoal) {
+    let point = buffer.offset_to_point(offset);
+    let goal_column = goal_column(buffer, point, goal);
+    if point.row == 0 {
+        return (ByteOffset(0), SelectionGoal::Column(goal_column));
+    }
+
+    let row = point.row.saturating_sub(rows);
+    (
+        buffer.point_to_offset(point_for_display_column(buffer, row, goal_column)),
+        SelectionGoal::Column(goal_column),
+    )
+}
+
+/// Move down by a number of rows, aiming for the goal column. Moving down from the last
+/// row goes to the end of the buffer.
+pub fn down(
+    buffer: &Buffer,
+    offset: ByteOffset,
+    goal: SelectionGoal,
+    rows: u32,
+) -> (ByteOffset, SelectionGoal) {
+    let point = buffer.offset_to_point(offset);
+    let goal_column = goal_column(buffer, point, goal);
+    let max_row = buffer.len_lines() - 1;
+    if point.row >= max_row {
+        return (buffer.len_bytes(), SelectionGoal::Column(goal_column));
+    }
+
+    let row = (point.row + rows).min(max_row);
+    (
+        buffer.point_to_offset(point_for_display_column(buffer, row, goal_column)),
+        SelectionGoal::Column(goal_column),
+    )
+}
+
+/// Find the smart start of a row: the first non-whitespace character, or column 0 if the
+/// cursor is already there
+pub fn line_beginning(buffer: &Buffer, offset: ByteOffset) -> ByteOffset {
+    let point = buffer.offset_to_point(offset);
+    let indent = buffer
+        .line(point.row)
+        .chars()
+        .take_while(|c| c.is_whitespace())
+        .map(char::len_utf8)
+        .sum::<usize>() as u32;
+    let column = if point.column == indent { 0 } else { indent };
+    buffer.point_to_offset(Point::new(point.row, column))
+}
+
+/// Find the end of a row, before its line break
+pub fn line_end(buffer: &Buffer, offset: ByteOffset) -> ByteOffset {
+    let row = buffer.offset_to_point(offset).row;
+    buffer.point_to_offset(Point::new(row, buffer.line_len(row)))
+}
+
+fn goal_column(buffer: &Buffer, point: Point, goal: SelectionGoal) -> u32 {
+    match goal {
+        SelectionGoal::Column(column) => column,
+        SelectionGoal::None => display_column(buffer, point),
+    }
+}
+
+/// The column a point is drawn at, counting chars and expanding tabs to the next tab stop
+pub fn display_column(buffer: &Buffer, point: Point) -> u32 {
+    buffer
+        .line(point.row)
+        .byte_slice(..point.column as usize)
+        .chars()
+        .fold(0, |column, c| column + char_display_width(c, column))
+}
+
+/// The point in a row drawn closest to a display column, clamped to the end of the row
+pub fn point_for_display_column(buffer: &Buffer, row: u32, target: u32) -> Point {
+    let mut column = 0;
+    let mut byte_column = 0;
+    for c in buffer.line(row).chars() {
+        let width = char_display_width(c, column);
+        if column + width > target {
+            // Land on whichever side of the char is nearer
+            if target - column > width / 2 {
+                byte_column += c.len_utf8();
+            }
+            break;
+        }
+        column += width;
+        byte_column += c.len_utf8();
+    }
+    Point::new(row, byte_column as u32)
+}
+
+fn char_display_width(c: char, column: u32) -> u32 {
+    if c == '\t' {
+        TAB_SIZE - column % TAB_SIZE
+    } else {
+        1
+    }
+}
+
+/// Byte ranges of the words within a row, using Unicode word boundaries and skipping
+/// whitespace. With `subword` set, words are split further at camelCase humps, digits
+/// and underscores.
+fn word_ranges(line: &str, subword: bool) -> Vec<Range<usize>> {
+    let words = line
+        .split_word_bound_indices()
+        .filter(|(_, word)| !word.chars().all(char::is_whitespace))
+        .map(|(start, word)| start..start + word.len());
+
+    if subword {
+        words
+            .flat_map(|range| subword_ranges(line, range))
+            .collect()
+    } else {
+        words.collect()
+    }
+}
+
+fn subword_ranges(line: &str, word: Range<usize>) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = None;
+    let mut chars = line[word.clone()].char_indices().peekable();
+    let mut prev: Option<char> = None;
+
+    while let Some((ix, c)) = chars.next() {
+        let ix = word.start + ix;
+        if c == '_' || c == '-' {
+            if let Some(start) = start.take() {
+                ranges.push(start..ix);
+            }
+        } else {
+            let next = chars.peek().map(|(_, c)| *c);
+            let is_hump = match prev {
+                Some(p) if p.is_lowercase() && c.is_uppercase() => true,
+                // The last capital of an acronym starts a new word, e.g. "HTTPServer"
+                Some(p) if p.is_uppercase() && c.is_uppercase() => {
+                    next.is_some_and(char::is_lowercase)
+                }
+                Some(p) if p.is_alphabetic() && c.is_ascii_digit() => true,
+                Some(p) if p.is_ascii_digit() && c.is_alphabetic() => true,
+                _ => false,
+            };
+            match start {
+                Some(s) if is_hump => {
+                    ranges.push(s..ix);
+                    start = Some(ix);
+                }
+                None => start = Some(ix),
+                _ => {}
+            }
+        }
+        prev = Some(c);
+    }
+
+    if let Some(start) = start {
+        ranges.push(start..word.end);
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Clusters of several chars, with some too long for one of the rope's chunks so
+    /// that chunk edges fall inside them
+    fn clustered_text() -> String {
+        let family = "👩\u{200D}👩\u{200D}👧\u{200D}👦";
+        let long_zwj = format!("{}👩", "👩\u{200D}".repeat(200));
+        let long_marks = format!("e{}", "\u{301}".repeat(700));
+        format!("ab{family}e\u{301}\u{302}🇳🇴 ").repeat(50) + &long_zwj + " " + &long_marks + " end"
+    }
+
+    #[test]
+    fn test_grapheme_boundaries_across_chunks() {
+        let text = clustered_text();
+        let buffer = Buffer::with_text(text.clone());
+        let boundaries = text
+            .grapheme_indices(true)
+            .map(|(ix, _)| ix)
+            .chain([text.len()])
+            .collect::<Vec<_>>();
+
+        let mut chunk_start = 0;
+        let split_clusters = buffer
+            .rope()
+            .chunks()
+            .filter(|chunk| {
+                chunk_start += chunk.len();
+                !boundaries.contains(&chunk_start)
+            })
+            .count();
+        assert!(split_clusters > 0, "no cluster spans a chunk edge");
+
+        for pair in boundaries.windows(2) {
+            let (prev, next) = (ByteOffset(pair[0]), ByteOffset(pair[1]));
+            assert_eq!(next_grapheme_boundary(&buffer, prev), next);
+            assert_eq!(prev_grapheme_boundary(&buffer, next), prev);
+        }
+        assert_eq!(
+            prev_grapheme_boundary(&buffer, ByteOffset(0)),
+            ByteOffset(0)
+        );
+        assert_eq!(
+            next_grapheme_boundary(&buffer, buffer.len_bytes()),
+            buffer.len_bytes()
+        );
+    }
+
+    #[test]
+    fn test_combining_marks() {
+        // A combining mark joins the char before it, even a space
+        let buffer = Buffer::with_text("e\u{301}\u{302} \u{301}x");
+        assert_eq!(
+            next_grapheme_boundary(&buffer, ByteOffset(0)),
+            ByteOffset(5)
+        );
+        assert_eq!(
+            next_grapheme_boundary(&buffer, ByteOffset(5)),
+            ByteOffset(8)
+        );
+        assert_eq!(
+            prev_grapheme_boundary(&buffer, ByteOffset(8)),
+            ByteOffset(5)
+        );
+        assert_eq!(
+            prev_grapheme_boundary(&buffer, ByteOffset(5)),
+            ByteOffset(0)
+        );
+    }
+
+    fn word_stops(text: &str, subword: bool) -> (Vec<usize>, Vec<usize>) {
+        let buffer = Buffer::with_text(text);
+        let mut ends = Vec::new();
+        let mut offset = ByteOffset(0);
+        while offset < buffer.len_bytes() {
+            offset = next_word_end(&buffer, offset, subword);
+            ends.push(offset.0);
+        }
+        let mut starts = Vec::new();
+        while offset > ByteOffset(0) {
+            offset = prev_word_start(&buffer, offset, subword);
+            starts.push(offset.0);
+        }
+        (ends, starts)
+    }
+
+    #[test]
+    fn test_word_motion() {
+        let (ends, starts) = word_stops("fooBar snake_case  HTTPServer", false);
+        assert_eq!(ends, [6, 17, 29]);
+        assert_eq!(starts, [19, 7, 0]);
+    }
+
+    #[test]
+    fn test_subword_motion() {
+        let (ends, starts) = word_stops("fooBar snake_case  HTTPServer v2x", true);
+        assert_eq!(ends, [3, 6, 12, 17, 23, 29, 31, 32, 33]);
+        assert_eq!(starts, [32, 31, 30, 23, 19, 13, 7, 3, 0]);
+        assert_eq!(subword_ranges("__private_name__", 0..16), [2..9, 10..14]);
+        assert_eq!(subword_ranges("kebab-case", 0..10), [0..5, 6..10]);
+    }
+
+    #[test]
+    fn test_punctuation_runs() {
+        // Punctuation stops word motion, one mark at a time
+        let (ends, starts) = word_stops("foo(bar) => baz;", false);
+        assert_eq!(ends, [3, 4, 7, 8, 10, 11, 15, 16]);
+        assert_eq!(starts, [15, 12, 10, 9, 7, 4, 3, 0]);
+
+        // A double-click picks a whole run of spaces, but a single mark
+        let buffer = Buffer::with_text("a   => b");
+        assert_eq!(
+            surrounding_word(&buffer, ByteOffset(2)),
+            ByteOffset(1)..ByteOffset(4)
+        );
+        assert_eq!(
+            surrounding_word(&buffer, ByteOffset(4)),
+            ByteOffset(4)..ByteOffset(5)
+        );
+        assert_eq!(
+            surrounding_word(&buffer, ByteOffset(8)),
+            ByteOffset(7)..ByteOffset(8)
+        );
+    }
+
+    #[test]
+    fn test_word_context_across_chunks() {
+        // Word motion on a long row matches the row's own segmentation
+        let text = clustered_text()
+            .replace(' ', "_x.y ")
+            .trim_end()
+            .to_string();
+        let buffer = Buffer::with_text(text.clone());
+        let mut offset = ByteOffset(0);
+        let mut ends = Vec::new();
+        while offset < buffer.len_bytes() {
+            offset = next_word_end(&buffer, offset, false);
+            ends.push(offset.0);
+        }
+        let expected = word_ranges(&text, false)
+            .into_iter()
+            .map(|range| range.end)
+            .collect::<Vec<_>>();
+        assert_eq!(ends, expected);
+    }
+
+    fn point(buffer: &Buffer, offset: ByteOffset) -> (u32, u32) {
+        let point = buffer.offset_to_point(offset);
+        (point.row, point.column)
+    }
+
+    #[test]
+    fn test_vertical_motion_keeps_goal_column() {
+        let buffer = Buffer::with_text("\tabc\nx\n  \tabcdef\nlast");
+        // After the "a" of the first row, drawn at column 5 past the tab
+        let start = ByteOffset(2);
+        let (offset, goal) = down(&buffer, start, SelectionGoal::None, 1);
+        assert_eq!(point(&buffer, offset), (1, 1));
+        assert_eq!(goal, SelectionGoal::Column(5));
+
+        // The short row doesn't lose the goal, which lands after the "a" again
+        let (offset, goal) = down(&buffer, offset, goal, 1);
+        assert_eq!(point(&buffer, offset), (2, 4));
+        let (offset, goal) = down(&buffer, offset, goal, 1);
+        assert_eq!(point(&buffer, offset), (3, 4));
+        let (offset, goal) = up(&buffer, offset, goal, 2);
+        assert_eq!(point(&buffer, offset), (1, 1));
+        let (offset, _) = up(&buffer, offset, goal, 10);
+        assert_eq!(point(&buffer, offset), (0, 2));
+    }
+
+    #[test]
+    fn test_vertical_motion_at_edges() {
+        let buffer = Buffer::with_text("first\nlast row");
+        let (offset, goal) = up(&buffer, ByteOffset(3), SelectionGoal::None, 1);
+        assert_eq!(offset, ByteOffset(0));
+        assert_eq!(goal, SelectionGoal::Column(3));
+
+        let (offset, goal) = down(&buffer, ByteOffset(9), SelectionGoal::None, 1);
+        assert_eq!(offset, buffer.len_bytes());
+        assert_eq!(goal, SelectionGoal::Column(3));
+        // The goal survives the jump, so coming back lands on the same column
+        let (offset, _) = up(&buffer, offset, goal, 1);
+        assert_eq!(point(&buffer, offset), (0, 3));
+    }
+
+    #[test]
+    fn test_display_columns_with_tabs() {
+        let buffer = Buffer::with_text("a\tb\n\tx");
+        assert_eq!(display_column(&buffer, Point::new(0, 1)), 1);
+        assert_eq!(display_column(&buffer, Point::new(0, 2)), TAB_SIZE);
+        assert_eq!(display_column(&buffer, Point::new(1, 1)), TAB_SIZE);
+        // A column inside a tab lands on whichever side is nearer
+        assert_eq!(point_for_display_column(&buffer, 1, 1), Point::new(1, 0));
+        assert_eq!(point_for_display_column(&buffer, 1, 3), Point::new(1, 1));
+        assert_eq!(point_for_display_column(&buffer, 1, 9), Point::new(1, 2));
+    }
+
+    #[test]
+    fn test_smart_line_beginning() {
+        let buffer = Buffer::with_text("    indented\nflush");
+        let indent = ByteOffset(4);
+        assert_eq!(line_beginning(&buffer, ByteOffset(9)), indent);
+        assert_eq!(line_beginning(&buffer, indent), ByteOffset(0));
+        assert_eq!(line_beginning(&buffer, ByteOffset(0)), indent);
+        assert_eq!(line_beginning(&buffer, ByteOffset(2)), indent);
+        assert_eq!(line_end(&buffer, ByteOffset(2)), ByteOffset(12));
+
+        // Without indentation both stops are column 0
+        assert_eq!(line_beginning(&buffer, ByteOffset(16)), ByteOffset(13));
+        assert_eq!(line_beginning(&buffer, ByteOffset(13)), ByteOffset(13));
+    }
+}
@@ -0,0 +1,306 @@
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/// Why a file couldn't be saved
+#[derive(Debug)]
+pub enum SaveError {
+    /// The buffer has never been saved and has no path to write to
+    NoPath,
+    /// The text has characters the chosen encoding can't represent
+    Unencodable {
+        encoding: &'static str,
+    },
+    PermissionDenied(PathBuf),
+    ReadOnlyFilesystem(PathBuf),
+    DiskFull(PathBuf),
+    /// The file was changed by something else since it was loaded
+    ChangedOnDisk(PathBuf),
+    Io(PathBuf, std::io::Error),
+}
+
+impl SaveError {
+    /// Classify an error from writing `path`
+    fn from_io(path: &Path, err: std::io::Error) -> Self {
+        let path = path.to_path_buf();
+        match err.kind() {
+            ErrorKind::PermissionDenied => Self::PermissionDenied(path),
+            ErrorKind::ReadOnlyFilesystem => Self::ReadOnlyFilesystem(path),
+            ErrorKind::StorageFull => Self::DiskFull(path),
+            _ => Self::Io(path, err),
+        }
+    }
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoPath => write!(f, "No file path set"),
+            Self::Unencodable { encoding } => {
+                write!(f, "Text can't be represented in {}", encoding)
+            }
+            Self::PermissionDenied(path) => {
+                write!(f, "Permission denied writing {}", path.display())
+            }
+            Self::ReadOnlyFilesystem(path) => {
+                write!(f, "{} is on a read-only filesystem", path.display())
+            }
+            Self::DiskFull(path) => write!(f, "No space left to write {}", path.display()),
+            Self::ChangedOnDisk(path) => {
+                write!(f, "{} was changed on disk", path.display())
+            }
+            Self::Io(path, err) => write!(f, "Couldn't write {}: {}", path.display(), err),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Replace a file's contents without ever leaving it half written. The bytes go to a
+/// temporary file beside the target, which is synced and renamed over it. Symlinks are
+/// followed so the link survives, and the original permissions and owner are kept.
+pub fn write_atomic(path: &Path, bytes: &[u8], keep_backup: bool) -> Result<(), SaveError> {
+    // Write through symlinks to the file they point at
+    let target = resolve_symlinks(path).map_err(|err| SaveError::from_io(path, err))?;
+    let existing = std::fs::metadata(&target).ok();
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    if keep_backup && existing.is_some() {
+        let backup = backup_path(&target);
+        std::fs::copy(&target, &backup).map_err(|err| SaveError::from_io(&backup, err))?;
+    }
+
+    let (temp_path, mut temp) = create_temp_file(dir, &target)?;
+    let result = (|| {
+        temp.write_all(bytes)?;
+        if let Some(metadata) = &existing {
+            temp.set_permissions(metadata.permissions())?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                // Only root can give a file away, so failing here keeps our own ownership
+                std::os::unix::fs::fchown(&temp, Some(metadata.uid()), Some(metadata.gid())).ok();
+            }
+        }
+        temp.sync_all()?;
+        drop(temp);
+        std::fs::rename(&temp_path, &target)
+    })();
+    if let Err(err) = result {
+        std::fs::remove_file(&temp_path).ok();
+        return Err(SaveError::from_io(&target, err));
+    }
+
+    // Make the rename itself durable
+    #[cfg(unix)]
+    if let Ok(dir) = File::open(dir) {
+        dir.sync_all().ok();
+    }
+    Ok(())
+}
+
+/// Follow a chain of symlinks to the path at its end, which may not exist yet. A link
+/// that dangles is kept by creating the file it points at.
+fn resolve_symlinks(path: &Path) -> std::io::Result<PathBuf> {
+    /// Links followed before giving up on a loop, as Linux does
+    const MAX_LINKS: usize = 40;
+
+    let mut target = path.to_path_buf();
+    for _ in 0..MAX_LINKS {
+        if !target.symlink_metadata().is_ok_and(|m| m.is_symlink()) {
+            return Ok(target);
+        }
+        // A relative link is relative to the directory holding it
+        let link = std::fs::read_link(&target)?;
+        target = match target.parent() {
+            Some(dir) => dir.join(link),
+            None => link,
+        };
+    }
+    Err(std::io::Error::other(format!(
+        "Too many levels of symlinks at {}",
+        path.display()
+    )))
+}
+
+/// Where the previous version of a file is kept, like `notes.txt~`
+fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push("~");
+    PathBuf::from(name)
+}
+
+/// Create a hidden file next to the target that nothing else is using
+fn create_temp_file(dir: &Path, target: &Path) -> Result<(PathBuf, File), SaveError> {
+    let name = target
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut attempt = 0;
+    loop {
+        let temp_path = dir.join(format!(".{}.{}.{}.tmp", name, std::process::id(), attempt));
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+        {
+            Ok(file) => return Ok((temp_path, file)),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists && attempt < 100 => attempt += 1,
+            Err(err) => return Err(SaveError::from_io(target, err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::test_support::TempDir;
+
+    #[test]
+    fn test_write_replaces_contents() {
+        let dir = TempDir::new("save-write");
+        let path = dir.join("notes.txt");
+        std::fs::write(&path, "old").unwrap();
+
+        write_atomic(&path, b"new", false).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+        // Only the file itself is left, with no temporary file or backup beside it
+        assert_eq!(std::fs::read_dir(&*dir).unwrap().count(), 1);
+
+        write_atomic(&dir.join("created.txt"), b"fresh", false).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.join("created.txt")).unwrap(),
+            "fresh"
+        );
+    }
+
+    #[test]
+    fn test_failed_write_keeps_original() {
+        let dir = TempDir::new("save-failure");
+        let path = dir.join("notes.txt");
+        std::fs::write(&path, "old").unwrap();
+        // Take every temporary name the save could use
+        for attempt in 0..=100 {
+            let name = format!(".notes.txt.{}.{}.tmp", std::process::id(), attempt);
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+
+        assert!(matches!(
+            write_atomic(&path, b"new", true),
+            Err(SaveError::Io(..))
+        ));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "old");
+        assert_eq!(std::fs::read_to_string(backup_path(&path)).unwrap(), "old");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new("save-mode");
+        let path = dir.join("script.sh");
+        std::fs::write(&path, "old").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o750)).unwrap();
+
+        write_atomic(&path, b"new", false).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o750);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_writes_through_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let dir = TempDir::new("save-symlink");
+        std::fs::create_dir(dir.join("sub")).unwrap();
+        let target = dir.join("real.txt");
+        std::fs::write(&target, "old").unwrap();
+        // A relative link, resolved from the directory it's in
+        let link = dir.join("sub").join("link.txt");
+        symlink("../real.txt", &link).unwrap();
+
+        write_atomic(&link, b"new", true).unwrap();
+        assert!(link.symlink_metadata().unwrap().is_symlink());
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "new");
+        // The backup sits beside the file, not the link
+        assert_eq!(
+            std::fs::read_to_string(dir.join("real.txt~")).unwrap(),
+            "old"
+        );
+
+        // A dangling link is kept, and the file it points at is created
+        let dangling = dir.join("dangling.txt");
+        symlink("missing.txt", &dangling).unwrap();
+        write_atomic(&dangling, b"created", false).unwrap();
+        assert!(dangling.symlink_metadata().unwrap().is_symlink());
+        assert_eq!(
+            std::fs::read_to_string(dir.join("missing.txt")).unwrap(),
+            "created"
+        );
+
+        // A loop of links is refused rather than followed forever
+        let (a, b) = (dir.join("a"), dir.join("b"));
+        symlink(&b, &a).unwrap();
+        symlink(&a, &b).unwrap();
+        assert!(write_atomic(&a, b"x", false).is_err());
+        assert!(a.symlink_metadata().unwrap().is_symlink());
+    }
+
+    #[test]
+    fn test_backup() {
+        let dir = TempDir::new("save-backup");
+        let path = dir.join("notes.txt");
+        std::fs::write(&path, "first").unwrap();
+
+        write_atomic(&path, b"second", true).unwrap();
+        write_atomic(&path, b"third", true).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "third");
+        assert_eq!(
+            std::fs::read_to_string(dir.join("notes.txt~")).unwrap(),
+            "second"
+        );
+
+        // A new file has nothing to back up
+        write_atomic(&dir.join("new.txt"), b"new", true).unwrap();
+        assert!(!dir.join("new.txt~").exists());
+    }
+
+    #[test]
+    fn test_error_classification() {
+        let path = Path::new("/somewhere/notes.txt");
+        let classify = |kind: ErrorKind| SaveError::from_io(path, std::io::Error::from(kind));
+        assert!(matches!(
+            classify(ErrorKind::PermissionDenied),
+            SaveError::PermissionDenied(p) if p == path
+        ));
+        assert!(matches!(
+            classify(ErrorKind::ReadOnlyFilesystem),
+            SaveError::ReadOnlyFilesystem(p) if p == path
+        ));
+        assert!(matches!(
+            classify(ErrorKind::StorageFull),
+            SaveError::DiskFull(p) if p == path
+        ));
+        assert!(matches!(
+            classify(ErrorKind::Interrupted),
+            SaveError::Io(p, _) if p == path
+        ));
+        assert_eq!(
+            SaveError::DiskFull(path.to_path_buf()).to_string(),
+            "No space left to write /somewhere/notes.txt"
+        );
+    }
+}
@@ -0,0 +1,77 @@
+use crate::components::ByteOffset;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// The column a cursor is trying to return to when moving between rows of different lengths
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SelectionGoal {
+    #[default]
+    None,
+    Column(u32),
+}
+
+/// A selected range of a buffer. An empty selection is a plain cursor.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Selection {
+    /// Where the selection was started; stays put while the selection is extended
+    pub anchor: ByteOffset,
+    /// Where the cursor is; moves when the selection is extended
+    pub head: ByteOffset,
+    pub goal: SelectionGoal,
+}
+
+impl Selection {
+    /// Create an empty selection at an offset
+    pub fn cursor(offset: ByteOffset) -> Self {
+        Self {
+            anchor: offset,
+            head: offset,
+            goal: SelectionGoal::None,
+        }
+    }
+
+    /// Create a selection from an anchor to a head
+    pub fn new(anchor: ByteOffset, head: ByteOffset) -> Self {
+        Self {
+            anchor,
+            head,
+            goal: SelectionGoal::None,
+        }
+    }
+
+    /// Check if the selection is just a cursor
+    pub fn is_empty(&self) -> bool {
+        self.anchor == self.head
+    }
+
+    /// Check if the head comes before the anchor
+    pub fn is_reversed(&self) -> bool {
+        self.head < self.anchor
+    }
+
+    pub fn start(&self) -> ByteOffset {
+        self.anchor.min(self.head)
+    }
+
+    pub fn end(&self) -> ByteOffset {
+        self.anchor.max(self.head)
+    }
+
+    /// Get the selected range in buffer order
+    pub fn range(&self) -> Range<ByteOffset> {
+        self.start()..self.end()
+    }
+
+    /// Move the head, keeping the anchor where it is
+    pub fn select_to(&mut self, offset: ByteOffset, goal: SelectionGoal) {
+        self.head = offset;
+        self.goal = goal;
+    }
+
+    /// Collapse the selection to a cursor at an offset
+    pub fn collapse_to(&mut self, offset: ByteOffset, goal: SelectionGoal) {
+        self.anchor = offset;
+        self.head = offset;
+        self.goal = goal;
+    }
+}
@@ -0,0 +1,47 @@
+use crate::components::LineNumbers;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+/// Preferences read from `settings.json` in the user's config directory. Anything left out
+/// keeps its default.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Whether word motion also stops at camelCase humps and underscores
+    pub subword_navigation: bool,
+    /// How the editor's gutter numbers rows: "absolute", "relative", "hybrid" or "off"
+    pub line_numbers: LineNumbers,
+    /// Whether saving keeps the previous version of a file as `file~`
+    pub keep_backup: bool,
+    /// Gitignore-style globs for paths the worktree hides, on top of the project's ignore
+    /// files
+    pub exclude_globs: Vec<String>,
+    /// Whether the worktree expands and indexes symlinked directories
+    pub follow_symlinks: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            subword_navigation: false,
+            line_numbers: LineNumbers::default(),
+            keep_backup: false,
+            exclude_globs: Vec::new(),
+            follow_symlinks: true,
+        }
+    }
+}
+
+impl Settings {
+    /// Read the settings, falling back to the defaults when the file is missing or invalid
+    pub fn load() -> Self {
+        settings_path()
+            .and_then(|path| std::fs::read(path).ok())
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .unwrap_or_default()
+    }
+}
+
+fn settings_path() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("poem").join("settings.json"))
+}
@@ -1,6 +1,22 @@
-use crate::components::Buffer;
+use crate::components::diff_view::LineDiff;
+use crate::components::disk_state::DiskChange;
+use crate::components::editor_element::{EditorElement, EditorLayout, ScrollbarLayout};
+use crate::components::encoding::{self, TextEncoding};
+use crate::components::git_status::GitStatuses;
+use crate::components::history::{EditKind, TransactionId};
+use crate::components::history_store::HistoryStore;
+use crate::components::line_ending;
+use crate::components::movement;
+use crate::components::worktree::DraggedEntry;
+use crate::components::{
+    Buffer, ByteOffset, OffsetUtf16, Point, SaveError, Selection, SelectionGoal,
+};
+use encoding_rs::Encoding;
 use gpui::{prelude::*, *};
-use std::path::PathBuf;
+use serde::Deserialize;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 // Define actions for the text editor
 actions!(
@@ -10,8 +26,29 @@ actions!(
         Delete,
         MoveLeft,
         MoveRight,
-        MoveToStart,
-        MoveToEnd,
+        MoveToPreviousWordStart,
+        MoveToNextWordEnd,
+        DeleteToPreviousWordStart,
+        DeleteToNextWordEnd,
+        MoveUp,
+        MoveDown,
+        MoveToBeginningOfLine,
+        MoveToEndOfLine,
+        MoveToBeginningOfDocument,
+        MoveToEndOfDocument,
+        PageUp,
+        PageDown,
+        SelectLeft,
+        SelectRight,
+        SelectUp,
+        SelectDown,
+        SelectToPreviousWordStart,
+        SelectToNextWordEnd,
+        SelectToBeginningOfLine,
+        SelectToEndOfLine,
+        SelectToBeginningOfDocument,
+        SelectToEndOfDocument,
+        SelectAll,
         Newline,
         Paste,
         Copy,
@@ -19,112 +56,841 @@ actions!(
         Save,
         Undo,
         Redo,
+        CycleLineEnding,
+        ReopenWithEncoding,
+        SaveWithEncoding,
+        CycleLineNumbers,
     ]
 );
 
+/// How the gutter numbers each row
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineNumbers {
+    /// Every row shows its own line number
+    #[default]
+    Absolute,
+    /// Every row shows its distance from the cursor's row
+    Relative,
+    /// The cursor's row shows its line number and the others their distance from it
+    Hybrid,
+    /// No gutter at all
+    Off,
+}
+
+impl LineNumbers {
+    /// The mode to switch to next when cycling through them
+    pub fn next(self) -> Self {
+        match self {
+            Self::Absolute => Self::Relative,
+            Self::Relative => Self::Hybrid,
+            Self::Hybrid => Self::Off,
+            Self::Off => Self::Absolute,
+        }
+    }
+}
+
+/// What choosing an encoding from the picker does
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EncodingPicker {
+    /// Decode the file on disk again
+    Reopen,
+    /// Write the buffer out in the chosen encoding
+    Save,
+}
+
+/// The file on disk compared with the buffer, for the conflict banner
+struct ConflictDiff {
+    theirs: String,
+    /// The buffer version the diff was worked out for
+    version: usize,
+    diff: LineDiff,
+}
+
+impl ConflictDiff {
+    fn new(buffer: &Buffer) -> Self {
+        let theirs = buffer.disk_text().unwrap_or_default();
+        Self {
+            diff: LineDiff::new(&buffer.to_string(), &theirs),
+            version: buffer.version(),
+            theirs,
+        }
+    }
+
+    /// Compare again if the buffer was edited since
+    fn refresh(&mut self, buffer: &Buffer) {
+        if self.version != buffer.version() {
+            self.diff = LineDiff::new(&buffer.to_string(), &self.theirs);
+            self.version = buffer.version();
+        }
+    }
+}
+
 /// A text editor component that provides UI for editing a Buffer
 pub struct TextEditor {
     focus_handle: FocusHandle,
     buffer: Buffer,
-    cursor: usize, // Cursor position in bytes
+    selection: Selection,
+    /// Range of text being composed by an IME
+    marked_range: Option<Range<ByteOffset>>,
+    /// Whether word motion also stops at camelCase humps and underscores
+    subword_navigation: bool,
+    /// Whether a mouse drag is extending the selection
+    is_selecting: bool,
+    /// The row a line-wise drag started on, including its line break
+    line_drag_anchor: Option<Range<ByteOffset>>,
+    line_numbers: LineNumbers,
+    /// Where undo history is kept between sessions
+    history_store: Option<HistoryStore>,
+    /// Rows shaped by the last paint, for mapping mouse positions to offsets
+    last_layout: Option<EditorLayout>,
+    /// Number of rows that fit in the viewport, used for paging
+    visible_rows: u32,
+    /// How far the content is scrolled from the top left
+    scroll_position: gpui::Point<Pixels>,
+    /// Set when the cursor moves, so the next paint scrolls it into view
+    autoscroll_requested: bool,
+    /// Distance from the top of the scrollbar thumb to where it was grabbed, while dragging it
+    scrollbar_drag: Option<Pixels>,
+    /// Shown while choosing an encoding to reopen or save the file with
+    encoding_picker: Option<EncodingPicker>,
+    /// Why the last open or save failed
+    error: Option<String>,
+    /// How the file on disk differs from the unsaved buffer, until the user picks a side
+    disk_conflict: Option<DiskChange>,
+    /// The difference between the two versions, while the conflict banner is showing it
+    conflict_diff: Option<ConflictDiff>,
+    /// What git reports for the worktree the open file belongs to
+    git_statuses: Option<Arc<GitStatuses>>,
 }
 
 impl TextEditor {
     pub fn new(cx: &mut Context<Self>) -> Self {
-        Self {
-            focus_handle: cx.focus_handle(),
-            buffer: Buffer::new(),
-            cursor: 0,
-        }
+        Self::with_buffer(Buffer::new(), ByteOffset(0), cx)
     }
 
     pub fn with_text(text: impl Into<String>, cx: &mut Context<Self>) -> Self {
         let buffer = Buffer::with_text(text);
         let cursor = buffer.len_bytes();
+        Self::with_buffer(buffer, cursor, cx)
+    }
+
+    fn with_buffer(buffer: Buffer, cursor: ByteOffset, cx: &mut Context<Self>) -> Self {
+        // Keep the open file's undo history when the app quits or the window is closed,
+        // not only when switching files or saving
+        cx.on_app_quit(|this, _cx| {
+            this.persist_history();
+            async {}
+        })
+        .detach();
+        cx.on_release(|this, _cx| this.persist_history()).detach();
+
         Self {
             focus_handle: cx.focus_handle(),
             buffer,
-            cursor,
+            selection: Selection::cursor(cursor),
+            marked_range: None,
+            subword_navigation: false,
+            is_selecting: false,
+            line_drag_anchor: None,
+            line_numbers: LineNumbers::default(),
+            history_store: None,
+            last_layout: None,
+            visible_rows: 1,
+            scroll_position: gpui::Point::default(),
+            autoscroll_requested: false,
+            scrollbar_drag: None,
+            encoding_picker: None,
+            error: None,
+            disk_conflict: None,
+            conflict_diff: None,
+            git_statuses: None,
         }
     }
 
+    pub fn subword_navigation(mut self, enabled: bool) -> Self {
+        self.subword_navigation = enabled;
+        self
+    }
+
+    pub fn line_numbers(mut self, mode: LineNumbers) -> Self {
+        self.line_numbers = mode;
+        self
+    }
+
+    pub fn keep_backup(mut self, enabled: bool) -> Self {
+        self.buffer.set_keep_backup(enabled);
+        self
+    }
+
+    /// Keep undo history in a store, so it is restored when a file is reopened unchanged
+    pub fn set_history_store(&mut self, store: HistoryStore) {
+        self.history_store = Some(store);
+    }
+
+    /// Use freshly read git statuses for the status shown in the header
+    pub fn set_git_statuses(&mut self, statuses: Arc<GitStatuses>, cx: &mut Context<Self>) {
+        self.git_statuses = Some(statuses);
+        cx.notify();
+    }
+
     pub fn open_file(&mut self, path: PathBuf, cx: &mut Context<Self>) {
-        if let Ok(()) = self.buffer.load_file(path) {
-            self.cursor = 0;
+        self.persist_history();
+        let result = self.buffer.load_file(path);
+        if result.is_ok() {
+            self.restore_history();
+        }
+        self.after_load(result, cx);
+    }
+
+    /// Open the first file dropped onto the editor
+    fn open_dropped(&mut self, paths: &[PathBuf], cx: &mut Context<Self>) {
+        if let Some(path) = paths.iter().find(|path| path.is_file()) {
+            self.open_file(path.clone(), cx);
+        }
+    }
+
+    /// Reset the view for newly loaded text, or report why loading failed
+    fn after_load(&mut self, result: std::io::Result<()>, cx: &mut Context<Self>) {
+        match result {
+            Ok(()) => {
+                self.selection = Selection::cursor(ByteOffset(0));
+                self.marked_range = None;
+                self.scroll_position = gpui::Point::default();
+                self.error = None;
+                self.clear_disk_conflict();
+            }
+            Err(err) => self.error = Some(err.to_string()),
+        }
+        cx.notify();
+    }
+
+    /// Follow the open file when it, or a directory containing it, is renamed or moved
+    pub fn file_moved(&mut self, from: &Path, to: &Path, cx: &mut Context<Self>) {
+        let Some(rest) = self
+            .buffer
+            .file_path()
+            .and_then(|path| path.strip_prefix(from).ok())
+        else {
+            return;
+        };
+        let path = if rest.as_os_str().is_empty() {
+            to.to_path_buf()
+        } else {
+            to.join(rest)
+        };
+        self.buffer.set_file_path(path);
+        cx.notify();
+    }
+
+    /// Flag the open file as deleted when it, or a directory containing it, is removed
+    pub fn file_deleted(&mut self, path: &Path, cx: &mut Context<Self>) {
+        if self
+            .buffer
+            .file_path()
+            .is_some_and(|file_path| file_path.starts_with(path))
+            && self.set_disk_conflict(DiskChange::Deleted)
+        {
             cx.notify();
         }
     }
 
+    /// Look for changes to the open file made outside the editor. An unmodified buffer
+    /// picks them up, while one with unsaved changes asks which version to keep.
+    pub fn check_disk(&mut self, cx: &mut Context<Self>) {
+        match self.buffer.disk_change() {
+            DiskChange::Unchanged => {
+                if self.disk_conflict.is_some() {
+                    self.clear_disk_conflict();
+                    cx.notify();
+                }
+            }
+            DiskChange::Modified if !self.buffer.is_dirty() => self.load_theirs(cx),
+            change => {
+                if self.set_disk_conflict(change) {
+                    cx.notify();
+                }
+            }
+        }
+    }
+
+    /// Flag a conflict with the file on disk, comparing against its latest text if a
+    /// comparison is open. Returns whether anything shown changed.
+    fn set_disk_conflict(&mut self, change: DiskChange) -> bool {
+        if self.disk_conflict != Some(change) {
+            self.disk_conflict = Some(change);
+            self.conflict_diff = None;
+            true
+        } else if self.conflict_diff.is_some() {
+            // The file changed again while being compared
+            self.conflict_diff = Some(ConflictDiff::new(&self.buffer));
+            true
+        } else {
+            false
+        }
+    }
+
+    fn clear_disk_conflict(&mut self) {
+        self.disk_conflict = None;
+        self.conflict_diff = None;
+    }
+
+    /// Resolve a conflict by keeping the buffer, so the next save overwrites the file
+    fn keep_mine(&mut self, cx: &mut Context<Self>) {
+        self.buffer.ignore_disk_changes();
+        self.clear_disk_conflict();
+        cx.notify();
+    }
+
+    /// Show or hide the difference between the buffer and the file on disk
+    fn toggle_conflict_diff(&mut self, cx: &mut Context<Self>) {
+        self.conflict_diff = match self.conflict_diff {
+            Some(_) => None,
+            None => Some(ConflictDiff::new(&self.buffer)),
+        };
+        cx.notify();
+    }
+
+    /// Replace the buffer with the file on disk, as a change that can be undone
+    fn load_theirs(&mut self, cx: &mut Context<Self>) {
+        match self.buffer.reload(&self.selection) {
+            Ok(selection) => {
+                self.restore_selection(selection, cx);
+                self.clear_disk_conflict();
+                self.error = None;
+            }
+            Err(err) => self.error = Some(err.to_string()),
+        }
+        cx.notify();
+    }
+
+    fn render_disk_conflict(&self, change: DiskChange, cx: &mut Context<Self>) -> Div {
+        let message = match change {
+            DiskChange::Deleted => "The file was deleted on disk.",
+            _ => "The file changed on disk and you have unsaved changes.",
+        };
+        let button = |label: &'static str| {
+            div()
+                .px_2()
+                .py_1()
+                .rounded_md()
+                .bg(white())
+                .border_1()
+                .border_color(rgb(0xe0c36b))
+                .text_color(rgb(0x333333))
+                .cursor_pointer()
+                .hover(|style| style.bg(rgb(0xfff3cc)))
+                .child(label)
+        };
+
+        let mut banner = div()
+            .flex()
+            .items_center()
+            .gap_2()
+            .p_2()
+            .bg(rgb(0xfff8e1))
+            .border_b_1()
+            .border_color(rgb(0xe0c36b))
+            .text_sm()
+            .child(div().flex_1().text_color(rgb(0x5c4400)).child(message))
+            .child(button("Keep Mine").on_mouse_down(
+                MouseButton::Left,
+                cx.listener(|this, _event: &MouseDownEvent, _window, cx| this.keep_mine(cx)),
+            ));
+        if change == DiskChange::Modified {
+            banner = banner
+                .child(button("Load Theirs").on_mouse_down(
+                    MouseButton::Left,
+                    cx.listener(|this, _event: &MouseDownEvent, _window, cx| this.load_theirs(cx)),
+                ))
+                .child(button("Compare").on_mouse_down(
+                    MouseButton::Left,
+                    cx.listener(|this, _event: &MouseDownEvent, _window, cx| {
+                        this.toggle_conflict_diff(cx);
+                    }),
+                ));
+        }
+
+        let diff = self
+            .conflict_diff
+            .as_ref()
+            .filter(|_| change == DiskChange::Modified)
+            .map(|conflict_diff| {
+                div()
+                    .id("disk-conflict-diff")
+                    .max_h(px(240.))
+                    .overflow_y_scroll()
+                    .border_b_1()
+                    .border_color(rgb(0xe5e5e5))
+                    .child(conflict_diff.diff.render())
+            });
+
+        div().flex().flex_col().child(banner).children(diff)
+    }
+
+    /// Write the open file's undo history to the store
+    fn persist_history(&self) {
+        if let (Some(store), Some(path)) = (&self.history_store, self.buffer.file_path()) {
+            store
+                .save(path, self.buffer.rope(), self.buffer.history())
+                .ok();
+        }
+    }
+
+    /// Pick up the open file's undo history from the store, if it was saved for this text
+    fn restore_history(&mut self) {
+        let (Some(store), Some(path)) = (&self.history_store, self.buffer.file_path()) else {
+            return;
+        };
+        if let Some(history) = store.load(path, self.buffer.rope()) {
+            self.buffer.set_history(history);
+        }
+    }
+
     pub fn buffer(&self) -> &Buffer {
         &self.buffer
     }
 
-    pub fn buffer_mut(&mut self) -> &mut Buffer {
-        &mut self.buffer
+    pub fn selection(&self) -> &Selection {
+        &self.selection
     }
 
-    fn insert_char(&mut self, c: char, cx: &mut Context<Self>) {
-        self.buffer.insert_char(self.cursor, c);
-        self.cursor += c.len_utf8();
+    pub fn marked_range(&self) -> Option<Range<ByteOffset>> {
+        self.marked_range.clone()
+    }
+
+    pub fn line_number_mode(&self) -> LineNumbers {
+        self.line_numbers
+    }
+
+    /// Store the layout from the element's last paint
+    pub fn set_layout(&mut self, layout: EditorLayout) {
+        self.visible_rows =
+            ((layout.bounds.size.height / layout.line_height).floor() as u32).max(1);
+        self.last_layout = Some(layout);
+    }
+
+    pub fn scroll_position(&self) -> gpui::Point<Pixels> {
+        self.scroll_position
+    }
+
+    /// Set the scroll position without notifying, for the element to store the clamped position
+    pub fn set_scroll_position(&mut self, position: gpui::Point<Pixels>) {
+        self.scroll_position = position;
+    }
+
+    /// Check whether the cursor should be scrolled into view, clearing the request
+    pub fn take_autoscroll_request(&mut self) -> bool {
+        std::mem::take(&mut self.autoscroll_requested)
+    }
+
+    /// Scroll the cursor into view on the next paint
+    fn request_autoscroll(&mut self, cx: &mut Context<Self>) {
+        self.autoscroll_requested = true;
         cx.notify();
     }
 
+    fn cursor(&self) -> ByteOffset {
+        self.selection.head
+    }
+
+    fn move_to(&mut self, offset: ByteOffset, cx: &mut Context<Self>) {
+        self.selection.collapse_to(offset, SelectionGoal::None);
+        self.request_autoscroll(cx);
+    }
+
+    /// Restore the buffer to any state in its undo tree
+    pub fn jump_to_history(&mut self, id: TransactionId, cx: &mut Context<Self>) {
+        if let Some(selection) = self.buffer.jump_to(id) {
+            self.restore_selection(selection, cx);
+        }
+    }
+
+    /// Restore a selection saved with an undo transaction
+    fn restore_selection(&mut self, selection: Selection, cx: &mut Context<Self>) {
+        let len = self.buffer.len_bytes();
+        self.selection = Selection {
+            anchor: selection.anchor.min(len),
+            head: selection.head.min(len),
+            goal: selection.goal,
+        };
+        self.marked_range = None;
+        self.request_autoscroll(cx);
+    }
+
+    fn select_to(&mut self, offset: ByteOffset, cx: &mut Context<Self>) {
+        self.selection.select_to(offset, SelectionGoal::None);
+        self.request_autoscroll(cx);
+    }
+
+    /// Move the cursor up (negative) or down by a number of rows, keeping the goal column
+    fn move_vertically(&mut self, rows: i32, select: bool, cx: &mut Context<Self>) {
+        let (offset, goal) = if rows < 0 {
+            movement::up(
+                &self.buffer,
+                self.cursor(),
+                self.selection.goal,
+                rows.unsigned_abs(),
+            )
+        } else {
+            movement::down(
+                &self.buffer,
+                self.cursor(),
+                self.selection.goal,
+                rows as u32,
+            )
+        };
+        if select {
+            self.selection.select_to(offset, goal);
+        } else {
+            self.selection.collapse_to(offset, goal);
+        }
+        self.request_autoscroll(cx);
+    }
+
+    /// Run edits as one undo step that restores the current selection when undone. The kind
+    /// decides whether the step can merge with the one before.
+    fn transact(
+        &mut self,
+        kind: EditKind,
+        cx: &mut Context<Self>,
+        edit: impl FnOnce(&mut Self, &mut Context<Self>),
+    ) {
+        self.buffer.start_transaction(&self.selection, kind);
+        edit(self, cx);
+        self.buffer.end_transaction(&self.selection);
+    }
+
+    /// Replace a range, or the selection if there's none, leaving the cursor after the
+    /// new text
+    fn replace_range(
+        &mut self,
+        range: Option<Range<ByteOffset>>,
+        text: &str,
+        kind: EditKind,
+        cx: &mut Context<Self>,
+    ) {
+        self.transact(kind, cx, |this, cx| {
+            if let Some(range) = range {
+                this.selection = Selection::new(range.start, range.end);
+            }
+            this.replace_selection(text, cx);
+        });
+    }
+
+    /// Replace the selected text, leaving the cursor after the new text
+    fn replace_selection(&mut self, text: &str, cx: &mut Context<Self>) {
+        // Pasted text may come with other line endings; the buffer only holds `\n`
+        let text = &*line_ending::normalize(text);
+        self.transact(EditKind::Other, cx, |this, cx| {
+            this.marked_range = None;
+            let range = this.selection.range();
+            if !range.is_empty() {
+                this.buffer.remove(range.clone());
+            }
+            if !text.is_empty() {
+                this.buffer.insert(range.start, text);
+            }
+            this.move_to(range.start + text.len(), cx);
+        });
+    }
+
+    /// Delete the selection, or the range from the cursor to `offset` if nothing is selected
+    fn delete_to(&mut self, offset: ByteOffset, cx: &mut Context<Self>) {
+        self.transact(EditKind::Deletion, cx, |this, cx| {
+            if this.selection.is_empty() {
+                this.selection.select_to(offset, SelectionGoal::None);
+            }
+            this.replace_selection("", cx);
+        });
+    }
+
+    fn selected_text(&self) -> String {
+        self.buffer.slice(self.selection.range()).to_string()
+    }
+
+    fn insert_char(&mut self, c: char, cx: &mut Context<Self>) {
+        if self.selection.is_empty() {
+            self.transact(EditKind::Typing, cx, |this, cx| {
+                this.buffer.insert_char(this.cursor(), c);
+                this.move_to(this.cursor() + c.len_utf8(), cx);
+            });
+        } else {
+            self.replace_selection(c.encode_utf8(&mut [0; 4]), cx);
+        }
+    }
+
     fn insert_text(&mut self, text: &str, cx: &mut Context<Self>) {
-        self.buffer.insert(self.cursor, text);
-        self.cursor += text.len();
-        cx.notify();
+        self.replace_selection(text, cx);
+    }
+
+    /// Get the range of a row including its line break
+    fn row_range(&self, row: u32) -> Range<ByteOffset> {
+        let start = self.buffer.point_to_offset(Point::new(row, 0));
+        let end = if row + 1 < self.buffer.len_lines() {
+            self.buffer.point_to_offset(Point::new(row + 1, 0))
+        } else {
+            self.buffer.len_bytes()
+        };
+        start..end
+    }
+
+    fn range_to_utf16(&self, range: &Range<ByteOffset>) -> Range<usize> {
+        self.buffer.offset_to_offset_utf16(range.start).0
+            ..self.buffer.offset_to_offset_utf16(range.end).0
+    }
+
+    fn range_from_utf16(&self, range_utf16: &Range<usize>) -> Range<ByteOffset> {
+        self.buffer
+            .offset_utf16_to_offset(OffsetUtf16(range_utf16.start))
+            ..self
+                .buffer
+                .offset_utf16_to_offset(OffsetUtf16(range_utf16.end))
+    }
+
+    /// Select whole rows from the anchor row to the row containing `offset`
+    fn select_lines(
+        &mut self,
+        anchor: Range<ByteOffset>,
+        offset: ByteOffset,
+        cx: &mut Context<Self>,
+    ) {
+        let row = self.buffer.offset_to_point(offset).row;
+        let target = self.row_range(row);
+        self.selection = if target.start < anchor.start {
+            Selection::new(anchor.end, target.start)
+        } else {
+            Selection::new(anchor.start, target.end)
+        };
+        self.request_autoscroll(cx);
+    }
+
+    fn gutter_contains(&self, position: gpui::Point<Pixels>) -> bool {
+        self.line_numbers != LineNumbers::Off
+            && self
+                .last_layout
+                .as_ref()
+                .is_some_and(|layout| layout.gutter_bounds.contains(&position))
+    }
+
+    /// Map a window position to the nearest buffer offset using the last painted layout
+    fn offset_for_position(&self, position: gpui::Point<Pixels>) -> ByteOffset {
+        match &self.last_layout {
+            Some(layout) => self
+                .buffer
+                .point_to_offset(layout.point_for_position(position)),
+            None => self.cursor(),
+        }
     }
 
     // Action handlers
     fn backspace(&mut self, _: &Backspace, _window: &mut Window, cx: &mut Context<Self>) {
-        if self.cursor > 0 {
-            let text = self.buffer.to_string();
-            if let Some((idx, _)) = text[..self.cursor].char_indices().next_back() {
-                self.buffer.remove(idx..self.cursor);
-                self.cursor = idx;
-                cx.notify();
-            }
-        }
+        let start = movement::prev_grapheme_boundary(&self.buffer, self.cursor());
+        self.delete_to(start, cx);
     }
 
     fn delete(&mut self, _: &Delete, _window: &mut Window, cx: &mut Context<Self>) {
-        if self.cursor < self.buffer.len_chars() {
-            let text = self.buffer.to_string();
-            if let Some((_, c)) = text[self.cursor..].char_indices().next() {
-                self.buffer.remove(self.cursor..self.cursor + c.len_utf8());
-                cx.notify();
-            }
-        }
+        let end = movement::next_grapheme_boundary(&self.buffer, self.cursor());
+        self.delete_to(end, cx);
     }
 
     fn move_left(&mut self, _: &MoveLeft, _window: &mut Window, cx: &mut Context<Self>) {
-        if self.cursor > 0 {
-            let text = self.buffer.to_string();
-            if let Some((idx, _)) = text[..self.cursor].char_indices().next_back() {
-                self.cursor = idx;
-                cx.notify();
-            }
+        if self.selection.is_empty() {
+            self.move_to(
+                movement::prev_grapheme_boundary(&self.buffer, self.cursor()),
+                cx,
+            );
+        } else {
+            self.move_to(self.selection.start(), cx);
         }
     }
 
     fn move_right(&mut self, _: &MoveRight, _window: &mut Window, cx: &mut Context<Self>) {
-        let text = self.buffer.to_string();
-        if self.cursor < text.len() {
-            if let Some((_, c)) = text[self.cursor..].char_indices().next() {
-                self.cursor += c.len_utf8();
-                cx.notify();
-            }
+        if self.selection.is_empty() {
+            self.move_to(
+                movement::next_grapheme_boundary(&self.buffer, self.cursor()),
+                cx,
+            );
+        } else {
+            self.move_to(self.selection.end(), cx);
         }
     }
 
-    fn move_to_start(&mut self, _: &MoveToStart, _window: &mut Window, cx: &mut Context<Self>) {
-        self.cursor = 0;
-        cx.notify();
+    fn move_to_previous_word_start(
+        &mut self,
+        _: &MoveToPreviousWordStart,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let offset =
+            movement::prev_word_start(&self.buffer, self.cursor(), self.subword_navigation);
+        self.move_to(offset, cx);
     }
 
-    fn move_to_end(&mut self, _: &MoveToEnd, _window: &mut Window, cx: &mut Context<Self>) {
-        self.cursor = self.buffer.to_string().len();
-        cx.notify();
+    fn move_to_next_word_end(
+        &mut self,
+        _: &MoveToNextWordEnd,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let offset = movement::next_word_end(&self.buffer, self.cursor(), self.subword_navigation);
+        self.move_to(offset, cx);
+    }
+
+    fn delete_to_previous_word_start(
+        &mut self,
+        _: &DeleteToPreviousWordStart,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let start = movement::prev_word_start(&self.buffer, self.cursor(), self.subword_navigation);
+        self.delete_to(start, cx);
+    }
+
+    fn delete_to_next_word_end(
+        &mut self,
+        _: &DeleteToNextWordEnd,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let end = movement::next_word_end(&self.buffer, self.cursor(), self.subword_navigation);
+        self.delete_to(end, cx);
+    }
+
+    fn move_up(&mut self, _: &MoveUp, _window: &mut Window, cx: &mut Context<Self>) {
+        self.move_vertically(-1, false, cx);
+    }
+
+    fn move_down(&mut self, _: &MoveDown, _window: &mut Window, cx: &mut Context<Self>) {
+        self.move_vertically(1, false, cx);
+    }
+
+    fn move_to_beginning_of_line(
+        &mut self,
+        _: &MoveToBeginningOfLine,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.move_to(movement::line_beginning(&self.buffer, self.cursor()), cx);
+    }
+
+    fn move_to_end_of_line(
+        &mut self,
+        _: &MoveToEndOfLine,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.move_to(movement::line_end(&self.buffer, self.cursor()), cx);
+    }
+
+    fn move_to_beginning_of_document(
+        &mut self,
+        _: &MoveToBeginningOfDocument,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.move_to(ByteOffset(0), cx);
+    }
+
+    fn move_to_end_of_document(
+        &mut self,
+        _: &MoveToEndOfDocument,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.move_to(self.buffer.len_bytes(), cx);
+    }
+
+    fn page_up(&mut self, _: &PageUp, _window: &mut Window, cx: &mut Context<Self>) {
+        self.move_vertically(-(self.visible_rows as i32), false, cx);
+    }
+
+    fn page_down(&mut self, _: &PageDown, _window: &mut Window, cx: &mut Context<Self>) {
+        self.move_vertically(self.visible_rows as i32, false, cx);
+    }
+
+    fn select_left(&mut self, _: &SelectLeft, _window: &mut Window, cx: &mut Context<Self>) {
+        self.select_to(
+            movement::prev_grapheme_boundary(&self.buffer, self.cursor()),
+            cx,
+        );
+    }
+
+    fn select_right(&mut self, _: &SelectRight, _window: &mut Window, cx: &mut Context<Self>) {
+        self.select_to(
+            movement::next_grapheme_boundary(&self.buffer, self.cursor()),
+            cx,
+        );
+    }
+
+    fn select_to_previous_word_start(
+        &mut self,
+        _: &SelectToPreviousWordStart,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let offset =
+            movement::prev_word_start(&self.buffer, self.cursor(), self.subword_navigation);
+        self.select_to(offset, cx);
+    }
+
+    fn select_to_next_word_end(
+        &mut self,
+        _: &SelectToNextWordEnd,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let offset = movement::next_word_end(&self.buffer, self.cursor(), self.subword_navigation);
+        self.select_to(offset, cx);
+    }
+
+    fn select_up(&mut self, _: &SelectUp, _window: &mut Window, cx: &mut Context<Self>) {
+        self.move_vertically(-1, true, cx);
+    }
+
+    fn select_down(&mut self, _: &SelectDown, _window: &mut Window, cx: &mut Context<Self>) {
+        self.move_vertically(1, true, cx);
+    }
+
+    fn select_to_beginning_of_line(
+        &mut self,
+        _: &SelectToBeginningOfLine,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.select_to(movement::line_beginning(&self.buffer, self.cursor()), cx);
+    }
+
+    fn select_to_end_of_line(
+        &mut self,
+        _: &SelectToEndOfLine,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.select_to(movement::line_end(&self.buffer, self.cursor()), cx);
+    }
+
+    fn select_to_beginning_of_document(
+        &mut self,
+        _: &SelectToBeginningOfDocument,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.select_to(ByteOffset(0), cx);
+    }
+
+    fn select_to_end_of_document(
+        &mut self,
+        _: &SelectToEndOfDocument,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.select_to(self.buffer.len_bytes(), cx);
+    }
+
+    fn select_all(&mut self, _: &SelectAll, _window: &mut Window, cx: &mut Context<Self>) {
+        self.selection = Selection::new(ByteOffset(0), self.buffer.len_bytes());
+        self.request_autoscroll(cx);
     }
 
     fn newline(&mut self, _: &Newline, _window: &mut Window, cx: &mut Context<Self>) {
@@ -138,55 +904,409 @@ impl TextEditor {
     }
 
     fn copy(&mut self, _: &Copy, _window: &mut Window, cx: &mut Context<Self>) {
-        let text = self.buffer.to_string();
-        cx.write_to_clipboard(ClipboardItem::new_string(text));
+        if !self.selection.is_empty() {
+            cx.write_to_clipboard(ClipboardItem::new_string(self.selected_text()));
+        }
     }
 
     fn cut(&mut self, _: &Cut, _window: &mut Window, cx: &mut Context<Self>) {
-        let text = self.buffer.to_string();
-        cx.write_to_clipboard(ClipboardItem::new_string(text));
-        self.buffer.set_text("");
-        self.cursor = 0;
-        cx.notify();
+        if !self.selection.is_empty() {
+            cx.write_to_clipboard(ClipboardItem::new_string(self.selected_text()));
+            self.replace_selection("", cx);
+        }
     }
 
     fn save(&mut self, _: &Save, _window: &mut Window, cx: &mut Context<Self>) {
-        if self.buffer.save().is_ok() {
-            cx.notify();
+        self.save_with(Buffer::save, cx);
+    }
+
+    /// Save the buffer one way or another. A file deleted from disk is only written again
+    /// once the buffer is kept with Keep Mine.
+    fn save_with(
+        &mut self,
+        save: impl FnOnce(&mut Buffer) -> Result<(), SaveError>,
+        cx: &mut Context<Self>,
+    ) {
+        if self.disk_conflict == Some(DiskChange::Deleted) {
+            return;
+        }
+        let result = save(&mut self.buffer);
+        self.after_save(result, cx);
+    }
+
+    fn after_save(&mut self, result: Result<(), SaveError>, cx: &mut Context<Self>) {
+        match result {
+            Ok(()) => {
+                self.persist_history();
+                self.error = None;
+                self.clear_disk_conflict();
+            }
+            // Ask which version to keep rather than overwriting the other one
+            Err(SaveError::ChangedOnDisk(_)) => {
+                self.set_disk_conflict(DiskChange::Modified);
+            }
+            Err(err) => self.error = Some(err.to_string()),
+        }
+        cx.notify();
+    }
+
+    fn reopen_with_encoding(
+        &mut self,
+        _: &ReopenWithEncoding,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.toggle_encoding_picker(EncodingPicker::Reopen, cx);
+    }
+
+    fn save_with_encoding(
+        &mut self,
+        _: &SaveWithEncoding,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.toggle_encoding_picker(EncodingPicker::Save, cx);
+    }
+
+    fn toggle_encoding_picker(&mut self, picker: EncodingPicker, cx: &mut Context<Self>) {
+        if self.encoding_picker == Some(picker) {
+            self.encoding_picker = None;
+        } else {
+            self.encoding_picker = Some(picker);
         }
+        cx.notify();
+    }
+
+    /// Apply the encoding chosen from the picker
+    fn pick_encoding(&mut self, encoding: &'static Encoding, cx: &mut Context<Self>) {
+        match self.encoding_picker.take() {
+            Some(EncodingPicker::Reopen) => {
+                let result = self.buffer.reopen_with_encoding(encoding);
+                self.after_load(result, cx);
+            }
+            Some(EncodingPicker::Save) => self.save_with(
+                |buffer| buffer.save_with_encoding(TextEncoding::new(encoding)),
+                cx,
+            ),
+            None => {}
+        }
+    }
+
+    fn render_encoding_picker(&self, picker: EncodingPicker, cx: &mut Context<Self>) -> Div {
+        let title = match picker {
+            EncodingPicker::Reopen => "Reopen with encoding:",
+            EncodingPicker::Save => "Save with encoding:",
+        };
+        let current = self.buffer.encoding().encoding;
+
+        div()
+            .flex()
+            .flex_wrap()
+            .items_center()
+            .gap_1()
+            .p_2()
+            .border_b_1()
+            .border_color(rgb(0xe5e5e5))
+            .bg(rgb(0xf9f9f9))
+            .text_xs()
+            .child(div().text_color(rgb(0x666666)).child(title))
+            .children(encoding::ENCODINGS.iter().map(|&encoding| {
+                div()
+                    .px_1()
+                    .rounded_sm()
+                    .cursor_pointer()
+                    .text_color(rgb(0x333333))
+                    .when(encoding == current, |div| div.bg(rgb(0xe0ecff)))
+                    .hover(|style| style.bg(rgb(0xe5e5e5)))
+                    .on_mouse_down(
+                        MouseButton::Left,
+                        cx.listener(move |this, _event: &MouseDownEvent, _window, cx| {
+                            this.pick_encoding(encoding, cx);
+                        }),
+                    )
+                    .child(encoding.name())
+            }))
+    }
+
+    /// Switch the line ending the file is saved with to the next one
+    fn cycle_line_ending(
+        &mut self,
+        _: &CycleLineEnding,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let line_ending = self.buffer.line_ending().next();
+        self.buffer.set_line_ending(line_ending);
+        cx.notify();
+    }
+
+    fn cycle_line_numbers(
+        &mut self,
+        _: &CycleLineNumbers,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.line_numbers = self.line_numbers.next();
+        cx.notify();
     }
 
     fn undo(&mut self, _: &Undo, _window: &mut Window, cx: &mut Context<Self>) {
-        if let Some(new_cursor) = self.buffer.undo() {
-            self.cursor = new_cursor;
-            cx.notify();
+        if let Some(selection) = self.buffer.undo() {
+            self.restore_selection(selection, cx);
         }
     }
 
     fn redo(&mut self, _: &Redo, _window: &mut Window, cx: &mut Context<Self>) {
-        if let Some(new_cursor) = self.buffer.redo() {
-            self.cursor = new_cursor;
-            cx.notify();
+        if let Some(selection) = self.buffer.redo() {
+            self.restore_selection(selection, cx);
+        }
+    }
+
+    // Mouse handlers
+    fn on_mouse_down(
+        &mut self,
+        event: &MouseDownEvent,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        window.focus(&self.focus_handle, cx);
+        if let Some(scrollbar) = self.scrollbar_at(event.position) {
+            self.start_scrollbar_drag(scrollbar, event.position, cx);
+            return;
+        }
+        let offset = self.offset_for_position(event.position);
+
+        // Clicking a line number selects its row; dragging extends the selection by rows
+        if self.gutter_contains(event.position) {
+            let row = self.buffer.offset_to_point(offset).row;
+            let line = self.row_range(row);
+            self.line_drag_anchor = Some(line.clone());
+            self.select_lines(line, offset, cx);
+            return;
+        }
+
+        match event.click_count {
+            1 if event.modifiers.shift => self.select_to(offset, cx),
+            1 => self.move_to(offset, cx),
+            2 => {
+                let word = movement::surrounding_word(&self.buffer, offset);
+                self.selection = Selection::new(word.start, word.end);
+                self.request_autoscroll(cx);
+            }
+            _ => {
+                let row = self.buffer.offset_to_point(offset).row;
+                let line = self.row_range(row);
+                self.selection = Selection::new(line.start, line.end);
+                self.request_autoscroll(cx);
+            }
+        }
+        self.is_selecting = true;
+    }
+
+    fn on_mouse_move(
+        &mut self,
+        event: &MouseMoveEvent,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        if event.pressed_button != Some(MouseButton::Left) {
+            return;
+        }
+        if let Some(grab) = self.scrollbar_drag {
+            self.drag_scrollbar(event.position.y - grab, cx);
+        } else if let Some(anchor) = self.line_drag_anchor.clone() {
+            self.select_lines(anchor, self.offset_for_position(event.position), cx);
+        } else if self.is_selecting {
+            self.select_to(self.offset_for_position(event.position), cx);
         }
     }
 
-    fn render_text_with_cursor(&self, is_focused: bool) -> String {
-        let text = self.buffer.to_string();
+    fn on_mouse_up(&mut self, _: &MouseUpEvent, _window: &mut Window, _cx: &mut Context<Self>) {
+        self.is_selecting = false;
+        self.line_drag_anchor = None;
+        self.scrollbar_drag = None;
+    }
+
+    fn on_scroll_wheel(
+        &mut self,
+        event: &ScrollWheelEvent,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let Some(layout) = &self.last_layout else {
+            return;
+        };
+        let delta = event.delta.pixel_delta(layout.line_height);
+        self.scroll_position = point(
+            (self.scroll_position.x - delta.x).clamp(px(0.), layout.scroll_max.x),
+            (self.scroll_position.y - delta.y).clamp(px(0.), layout.scroll_max.y),
+        );
+        cx.notify();
+    }
+
+    // Scrollbar
+    fn scrollbar_at(&self, position: gpui::Point<Pixels>) -> Option<ScrollbarLayout> {
+        let scrollbar = self.last_layout.as_ref()?.scrollbar?;
+        scrollbar.track.contains(&position).then_some(scrollbar)
+    }
+
+    /// Grab the thumb where it was clicked, or center it on the click when the track was hit
+    fn start_scrollbar_drag(
+        &mut self,
+        scrollbar: ScrollbarLayout,
+        position: gpui::Point<Pixels>,
+        cx: &mut Context<Self>,
+    ) {
+        let grab = if scrollbar.thumb.contains(&position) {
+            position.y - scrollbar.thumb.top()
+        } else {
+            scrollbar.thumb.size.height / 2.
+        };
+        self.scrollbar_drag = Some(grab);
+        self.drag_scrollbar(position.y - grab, cx);
+    }
+
+    fn drag_scrollbar(&mut self, thumb_top: Pixels, cx: &mut Context<Self>) {
+        let Some(layout) = &self.last_layout else {
+            return;
+        };
+        let Some(scrollbar) = layout.scrollbar else {
+            return;
+        };
+        self.scroll_position.y = scrollbar.scroll_y_for_thumb_top(thumb_top, layout.scroll_max.y);
+        cx.notify();
+    }
+}
+
+impl EntityInputHandler for TextEditor {
+    fn text_for_range(
+        &mut self,
+        range_utf16: Range<usize>,
+        adjusted_range: &mut Option<Range<usize>>,
+        _window: &mut Window,
+        _cx: &mut Context<Self>,
+    ) -> Option<String> {
+        let range = self.range_from_utf16(&range_utf16);
+        adjusted_range.replace(self.range_to_utf16(&range));
+        Some(self.buffer.slice(range).to_string())
+    }
+
+    fn selected_text_range(
+        &mut self,
+        _ignore_disabled_input: bool,
+        _window: &mut Window,
+        _cx: &mut Context<Self>,
+    ) -> Option<UTF16Selection> {
+        Some(UTF16Selection {
+            range: self.range_to_utf16(&self.selection.range()),
+            reversed: self.selection.is_reversed(),
+        })
+    }
+
+    fn marked_text_range(
+        &self,
+        _window: &mut Window,
+        _cx: &mut Context<Self>,
+    ) -> Option<Range<usize>> {
+        self.marked_range
+            .as_ref()
+            .map(|range| self.range_to_utf16(range))
+    }
 
-        if !is_focused {
-            // Don't show cursor when not focused
-            return if text.is_empty() { String::new() } else { text };
+    fn unmark_text(&mut self, _window: &mut Window, cx: &mut Context<Self>) {
+        self.marked_range = None;
+        self.buffer.finish_undo_group();
+        cx.notify();
+    }
+
+    fn replace_text_in_range(
+        &mut self,
+        range_utf16: Option<Range<usize>>,
+        text: &str,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let range = range_utf16
+            .map(|range_utf16| self.range_from_utf16(&range_utf16))
+            .or(self.marked_range.clone());
+        // Committing composed text ends the composition's undo step
+        if self.marked_range.is_some() {
+            self.replace_range(range, text, EditKind::Composition, cx);
+            self.buffer.finish_undo_group();
+        } else {
+            self.replace_range(range, text, EditKind::Typing, cx);
         }
+    }
+
+    fn replace_and_mark_text_in_range(
+        &mut self,
+        range_utf16: Option<Range<usize>>,
+        new_text: &str,
+        new_selected_range_utf16: Option<Range<usize>>,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let range = range_utf16
+            .map(|range_utf16| self.range_from_utf16(&range_utf16))
+            .or(self.marked_range.clone());
+        let start = range
+            .as_ref()
+            .map_or(self.selection.start(), |range| range.start);
+        self.buffer
+            .start_transaction(&self.selection, EditKind::Composition);
+        self.replace_range(range, new_text, EditKind::Composition, cx);
 
-        if text.is_empty() {
-            return "▎".to_string();
+        if !new_text.is_empty() {
+            self.marked_range = Some(start..start + new_text.len());
         }
+        // The new selection is relative to the composed text
+        if let Some(selected) = new_selected_range_utf16 {
+            let base = self.buffer.offset_to_offset_utf16(start);
+            let range = self.range_from_utf16(&(base.0 + selected.start..base.0 + selected.end));
+            self.selection = Selection::new(range.start, range.end);
+        }
+        self.buffer.end_transaction(&self.selection);
+        // Clearing the composed text cancels it, so the next composition is a new step
+        if self.marked_range.is_none() {
+            self.buffer.finish_undo_group();
+        }
+    }
 
-        // Insert cursor marker at cursor position
-        let before_cursor = &text[..self.cursor];
-        let after_cursor = &text[self.cursor..];
+    fn bounds_for_range(
+        &mut self,
+        range_utf16: Range<usize>,
+        _element_bounds: Bounds<Pixels>,
+        _window: &mut Window,
+        _cx: &mut Context<Self>,
+    ) -> Option<Bounds<Pixels>> {
+        let layout = self.last_layout.as_ref()?;
+        let range = self.range_from_utf16(&range_utf16);
+        let start = self.buffer.offset_to_point(range.start);
+        let end = self.buffer.offset_to_point(range.end);
+        // Measured from the text area, which starts right of the gutter
+        let top =
+            layout.bounds.top() + layout.line_height * start.row as f32 - layout.scroll_position.y;
+        let left = layout.bounds.left() - layout.scroll_position.x;
+        // Ranges spanning rows are reported as their first row
+        let end = if end.row == start.row {
+            end
+        } else {
+            Point::new(start.row, self.buffer.line_len(start.row))
+        };
+        Some(Bounds::from_corners(
+            point(left + layout.x_for_point(start), top),
+            point(left + layout.x_for_point(end), top + layout.line_height),
+        ))
+    }
 
-        format!("{}▎{}", before_cursor, after_cursor)
+    fn character_index_for_point(
+        &mut self,
+        point: gpui::Point<Pixels>,
+        _window: &mut Window,
+        _cx: &mut Context<Self>,
+    ) -> Option<usize> {
+        let offset = self.offset_for_position(point);
+        Some(self.buffer.offset_to_offset_utf16(offset).0)
     }
 }
 
@@ -198,18 +1318,29 @@ impl Focusable for TextEditor {
 
 impl Render for TextEditor {
     fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
-        let is_focused = self.focus_handle.is_focused(_window);
-        let content = self.render_text_with_cursor(is_focused);
-
+        if let Some(conflict_diff) = &mut self.conflict_diff {
+            conflict_diff.refresh(&self.buffer);
+        }
         let file_name = self.buffer.file_name().unwrap_or("Untitled");
 
         let dirty_indicator = if self.buffer.is_dirty() { " ●" } else { "" };
+        let git_status = self
+            .git_statuses
+            .as_ref()
+            .zip(self.buffer.file_path())
+            .and_then(|(statuses, path)| statuses.status(path, false));
 
         div()
             .flex()
             .flex_col()
             .w_full()
             .h_full()
+            .on_drop(cx.listener(|this, paths: &ExternalPaths, _window, cx| {
+                this.open_dropped(paths.paths(), cx)
+            }))
+            .on_drop(cx.listener(|this, entry: &DraggedEntry, _window, cx| {
+                this.open_dropped(std::slice::from_ref(&entry.path), cx)
+            }))
             // Header with file name
             .child(
                 div()
@@ -219,14 +1350,82 @@ impl Render for TextEditor {
                     .border_b_1()
                     .border_color(rgb(0xe5e5e5))
                     .bg(rgb(0xf9f9f9))
+                    .justify_between()
+                    .child(
+                        div()
+                            .flex()
+                            .items_center()
+                            .gap_2()
+                            .child(
+                                div()
+                                    .text_sm()
+                                    .font_weight(FontWeight::SEMIBOLD)
+                                    .text_color(rgb(0x333333))
+                                    .child(format!("{}{}", file_name, dirty_indicator)),
+                            )
+                            .when_some(git_status, |title, status| {
+                                title.child(
+                                    div()
+                                        .text_xs()
+                                        .text_color(status.color())
+                                        .child(status.label()),
+                                )
+                            })
+                            .when_some(self.error.clone(), |title, error| {
+                                title.child(div().text_xs().text_color(rgb(0xcc0000)).child(error))
+                            }),
+                    )
                     .child(
                         div()
-                            .text_sm()
-                            .font_weight(FontWeight::SEMIBOLD)
-                            .text_color(rgb(0x333333))
-                            .child(format!("{}{}", file_name, dirty_indicator)),
+                            .flex()
+                            .items_center()
+                            .gap_1()
+                            // Clicking the encoding offers to reopen the file with another
+                            .child(
+                                div()
+                                    .px_1()
+                                    .rounded_sm()
+                                    .text_xs()
+                                    .text_color(rgb(0x666666))
+                                    .cursor_pointer()
+                                    .hover(|style| style.bg(rgb(0xe5e5e5)))
+                                    .on_mouse_down(
+                                        MouseButton::Left,
+                                        cx.listener(|this, _event: &MouseDownEvent, window, cx| {
+                                            this.reopen_with_encoding(
+                                                &ReopenWithEncoding,
+                                                window,
+                                                cx,
+                                            );
+                                        }),
+                                    )
+                                    .child(self.buffer.encoding().label()),
+                            )
+                            // Clicking the line ending converts the file to the next one
+                            .child(
+                                div()
+                                    .px_1()
+                                    .rounded_sm()
+                                    .text_xs()
+                                    .text_color(rgb(0x666666))
+                                    .cursor_pointer()
+                                    .hover(|style| style.bg(rgb(0xe5e5e5)))
+                                    .on_mouse_down(
+                                        MouseButton::Left,
+                                        cx.listener(|this, _event: &MouseDownEvent, window, cx| {
+                                            this.cycle_line_ending(&CycleLineEnding, window, cx);
+                                        }),
+                                    )
+                                    .child(self.buffer.line_ending().label()),
+                            ),
                     ),
             )
+            .when_some(self.encoding_picker, |div, picker| {
+                div.child(self.render_encoding_picker(picker, cx))
+            })
+            .when_some(self.disk_conflict, |div, change| {
+                div.child(self.render_disk_conflict(change, cx))
+            })
             // Editor content
             .child(
                 div()
@@ -239,8 +1438,29 @@ impl Render for TextEditor {
                     .on_action(cx.listener(Self::delete))
                     .on_action(cx.listener(Self::move_left))
                     .on_action(cx.listener(Self::move_right))
-                    .on_action(cx.listener(Self::move_to_start))
-                    .on_action(cx.listener(Self::move_to_end))
+                    .on_action(cx.listener(Self::move_to_previous_word_start))
+                    .on_action(cx.listener(Self::move_to_next_word_end))
+                    .on_action(cx.listener(Self::delete_to_previous_word_start))
+                    .on_action(cx.listener(Self::delete_to_next_word_end))
+                    .on_action(cx.listener(Self::move_up))
+                    .on_action(cx.listener(Self::move_down))
+                    .on_action(cx.listener(Self::move_to_beginning_of_line))
+                    .on_action(cx.listener(Self::move_to_end_of_line))
+                    .on_action(cx.listener(Self::move_to_beginning_of_document))
+                    .on_action(cx.listener(Self::move_to_end_of_document))
+                    .on_action(cx.listener(Self::page_up))
+                    .on_action(cx.listener(Self::page_down))
+                    .on_action(cx.listener(Self::select_left))
+                    .on_action(cx.listener(Self::select_right))
+                    .on_action(cx.listener(Self::select_to_previous_word_start))
+                    .on_action(cx.listener(Self::select_to_next_word_end))
+                    .on_action(cx.listener(Self::select_up))
+                    .on_action(cx.listener(Self::select_down))
+                    .on_action(cx.listener(Self::select_to_beginning_of_line))
+                    .on_action(cx.listener(Self::select_to_end_of_line))
+                    .on_action(cx.listener(Self::select_to_beginning_of_document))
+                    .on_action(cx.listener(Self::select_to_end_of_document))
+                    .on_action(cx.listener(Self::select_all))
                     .on_action(cx.listener(Self::newline))
                     .on_action(cx.listener(Self::paste))
                     .on_action(cx.listener(Self::copy))
@@ -248,28 +1468,15 @@ impl Render for TextEditor {
                     .on_action(cx.listener(Self::save))
                     .on_action(cx.listener(Self::undo))
                     .on_action(cx.listener(Self::redo))
-                    // Handle regular character input
-                    .on_key_down(cx.listener(|this, event: &KeyDownEvent, _window, cx| {
-                        // Only handle regular character input here
-                        // Actions handle special keys
-                        let key = event.keystroke.key.as_str();
-                        if key == "space" {
-                            this.insert_char(' ', cx);
-                        } else if key.len() == 1 {
-                            if let Some(c) = key.chars().next() {
-                                // Only insert if it's a printable character
-                                if !c.is_control() {
-                                    this.insert_char(c, cx);
-                                }
-                            }
-                        }
-                    }))
-                    .on_mouse_down(
-                        MouseButton::Left,
-                        cx.listener(|this, _event: &MouseDownEvent, window, cx| {
-                            window.focus(&this.focus_handle, cx);
-                        }),
-                    )
+                    .on_action(cx.listener(Self::cycle_line_ending))
+                    .on_action(cx.listener(Self::cycle_line_numbers))
+                    .on_action(cx.listener(Self::reopen_with_encoding))
+                    .on_action(cx.listener(Self::save_with_encoding))
+                    .on_mouse_down(MouseButton::Left, cx.listener(Self::on_mouse_down))
+                    .on_mouse_move(cx.listener(Self::on_mouse_move))
+                    .on_mouse_up(MouseButton::Left, cx.listener(Self::on_mouse_up))
+                    .on_mouse_up_out(MouseButton::Left, cx.listener(Self::on_mouse_up))
+                    .on_scroll_wheel(cx.listener(Self::on_scroll_wheel))
                     .cursor(CursorStyle::IBeam)
                     // Styling
                     .p_4()
@@ -284,7 +1491,7 @@ impl Render for TextEditor {
                     // .when(is_focused, |div: Div| {
                     //     div.border_color(rgb(0x0066ff)).border_2()
                     // })
-                    .child(content),
+                    .child(EditorElement::new(cx.entity())),
             )
     }
 }
@@ -1,9 +1,24 @@
 use gpui::{prelude::*, *};
+use std::ops::Range;
+
+/// Emitted when the user finishes with the input
+pub enum TextInputEvent {
+    /// Enter was pressed
+    Confirm,
+    /// Escape was pressed
+    Cancel,
+}
 
 pub struct TextInput {
     focus_handle: FocusHandle,
     content: String,
     placeholder: String,
+    /// Drawn with less padding, to fit in a row of a list
+    compact: bool,
+    /// Selected byte range of the content; empty when it's just a cursor
+    selected_range: Range<usize>,
+    /// Byte range of text being composed by an IME
+    marked_range: Option<Range<usize>>,
 }
 
 impl TextInput {
@@ -12,6 +27,9 @@ impl TextInput {
             focus_handle: cx.focus_handle(),
             content: String::new(),
             placeholder: "Type here...".to_string(),
+            compact: false,
+            selected_range: 0..0,
+            marked_range: None,
         }
     }
 
@@ -20,28 +38,190 @@ impl TextInput {
         self
     }
 
-    #[allow(dead_code)]
+    pub fn compact(mut self) -> Self {
+        self.compact = true;
+        self
+    }
+
     pub fn content(&self) -> &str {
         &self.content
     }
 
-    #[allow(dead_code)]
     pub fn set_content(&mut self, content: impl Into<String>, cx: &mut Context<Self>) {
         self.content = content.into();
+        self.selected_range = self.content.len()..self.content.len();
+        self.marked_range = None;
         cx.notify();
     }
 
-    fn handle_input(&mut self, input: &str, cx: &mut Context<Self>) {
-        self.content.push_str(input);
-        cx.notify();
+    fn handle_backspace(&mut self, cx: &mut Context<Self>) {
+        if self.selected_range.is_empty() {
+            let cursor = self.selected_range.start;
+            let Some((start, _)) = self.content[..cursor].char_indices().next_back() else {
+                return;
+            };
+            self.selected_range = start..cursor;
+        }
+        self.replace_range(self.selected_range.clone(), "", cx);
     }
 
-    fn handle_backspace(&mut self, cx: &mut Context<Self>) {
-        self.content.pop();
+    fn replace_range(&mut self, range: Range<usize>, text: &str, cx: &mut Context<Self>) {
+        self.content.replace_range(range.clone(), text);
+        let cursor = range.start + text.len();
+        self.selected_range = cursor..cursor;
+        self.marked_range = None;
         cx.notify();
     }
+
+    fn offset_from_utf16(&self, offset: usize) -> usize {
+        offset_from_utf16(&self.content, offset)
+    }
+
+    fn offset_to_utf16(&self, offset: usize) -> usize {
+        self.content[..offset].encode_utf16().count()
+    }
+
+    fn range_to_utf16(&self, range: &Range<usize>) -> Range<usize> {
+        self.offset_to_utf16(range.start)..self.offset_to_utf16(range.end)
+    }
+
+    fn range_from_utf16(&self, range_utf16: &Range<usize>) -> Range<usize> {
+        self.offset_from_utf16(range_utf16.start)..self.offset_from_utf16(range_utf16.end)
+    }
+
+    /// Shape the content with the window's text style, for measuring character positions
+    fn shape_content(&self, window: &mut Window) -> ShapedLine {
+        let style = window.text_style();
+        let font_size = style.font_size.to_pixels(window.rem_size());
+        let run = style.to_run(self.content.len());
+        window
+            .text_system()
+            .shape_line(self.content.clone().into(), font_size, &[run], None)
+    }
+}
+
+/// Convert a UTF-16 offset into a byte offset within `text`
+fn offset_from_utf16(text: &str, offset: usize) -> usize {
+    let mut utf16_count = 0;
+    for (ix, c) in text.char_indices() {
+        if utf16_count >= offset {
+            return ix;
+        }
+        utf16_count += c.len_utf16();
+    }
+    text.len()
 }
 
+impl EntityInputHandler for TextInput {
+    fn text_for_range(
+        &mut self,
+        range_utf16: Range<usize>,
+        adjusted_range: &mut Option<Range<usize>>,
+        _window: &mut Window,
+        _cx: &mut Context<Self>,
+    ) -> Option<String> {
+        let range = self.range_from_utf16(&range_utf16);
+        adjusted_range.replace(self.range_to_utf16(&range));
+        Some(self.content[range].to_string())
+    }
+
+    fn selected_text_range(
+        &mut self,
+        _ignore_disabled_input: bool,
+        _window: &mut Window,
+        _cx: &mut Context<Self>,
+    ) -> Option<UTF16Selection> {
+        Some(UTF16Selection {
+            range: self.range_to_utf16(&self.selected_range),
+            reversed: false,
+        })
+    }
+
+    fn marked_text_range(
+        &self,
+        _window: &mut Window,
+        _cx: &mut Context<Self>,
+    ) -> Option<Range<usize>> {
+        self.marked_range
+            .as_ref()
+            .map(|range| self.range_to_utf16(range))
+    }
+
+    fn unmark_text(&mut self, _window: &mut Window, _cx: &mut Context<Self>) {
+        self.marked_range = None;
+    }
+
+    fn replace_text_in_range(
+        &mut self,
+        range_utf16: Option<Range<usize>>,
+        text: &str,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let range = range_utf16
+            .map(|range_utf16| self.range_from_utf16(&range_utf16))
+            .or(self.marked_range.clone())
+            .unwrap_or(self.selected_range.clone());
+        self.replace_range(range, text, cx);
+    }
+
+    fn replace_and_mark_text_in_range(
+        &mut self,
+        range_utf16: Option<Range<usize>>,
+        new_text: &str,
+        new_selected_range_utf16: Option<Range<usize>>,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let range = range_utf16
+            .map(|range_utf16| self.range_from_utf16(&range_utf16))
+            .or(self.marked_range.clone())
+            .unwrap_or(self.selected_range.clone());
+
+        self.replace_range(range.clone(), new_text, cx);
+        if !new_text.is_empty() {
+            self.marked_range = Some(range.start..range.start + new_text.len());
+        }
+        // The new selection is relative to the composed text
+        if let Some(selected) = new_selected_range_utf16 {
+            self.selected_range = range.start + offset_from_utf16(new_text, selected.start)
+                ..range.start + offset_from_utf16(new_text, selected.end);
+        }
+    }
+
+    fn bounds_for_range(
+        &mut self,
+        range_utf16: Range<usize>,
+        element_bounds: Bounds<Pixels>,
+        window: &mut Window,
+        _cx: &mut Context<Self>,
+    ) -> Option<Bounds<Pixels>> {
+        let range = self.range_from_utf16(&range_utf16);
+        let line = self.shape_content(window);
+        Some(Bounds::from_corners(
+            point(
+                element_bounds.left() + line.x_for_index(range.start),
+                element_bounds.top(),
+            ),
+            point(
+                element_bounds.left() + line.x_for_index(range.end),
+                element_bounds.top() + window.line_height(),
+            ),
+        ))
+    }
+
+    fn character_index_for_point(
+        &mut self,
+        _point: gpui::Point<Pixels>,
+        _window: &mut Window,
+        _cx: &mut Context<Self>,
+    ) -> Option<usize> {
+        None
+    }
+}
+
+impl EventEmitter<TextInputEvent> for TextInput {}
+
 impl Focusable for TextInput {
     fn focus_handle(&self, _cx: &App) -> FocusHandle {
         self.focus_handle.clone()
@@ -53,18 +233,35 @@ impl Render for TextInput {
         let content = self.content.clone();
         let placeholder = self.placeholder.clone();
         let is_focused = self.focus_handle.is_focused(_window);
+        let focus_handle = self.focus_handle.clone();
+        let input = cx.entity();
+
+        // Underline text that an IME is still composing
+        let marked = self.marked_range.clone().map(|range| {
+            (
+                range,
+                HighlightStyle {
+                    underline: Some(UnderlineStyle {
+                        thickness: px(1.),
+                        color: None,
+                        wavy: false,
+                    }),
+                    ..Default::default()
+                },
+            )
+        });
 
         div()
             .key_context("TextInput")
             .track_focus(&self.focus_handle)
             .cursor(CursorStyle::IBeam)
+            // Text arrives through the input handler; only editing keys are handled here
             .on_key_down(cx.listener(|this, event: &KeyDownEvent, _window, cx| {
-                if event.keystroke.key == "backspace" {
-                    this.handle_backspace(cx);
-                } else if event.keystroke.key == "space" {
-                    this.handle_input(" ", cx);
-                } else if event.keystroke.key.len() == 1 {
-                    this.handle_input(&event.keystroke.key, cx);
+                match event.keystroke.key.as_str() {
+                    "backspace" => this.handle_backspace(cx),
+                    "enter" => cx.emit(TextInputEvent::Confirm),
+                    "escape" => cx.emit(TextInputEvent::Cancel),
+                    _ => {}
                 }
             }))
             .on_mouse_down(
@@ -74,17 +271,36 @@ impl Render for TextInput {
                 }),
             )
             // Default minimal styling - can be overridden at call site
-            .px_3()
-            .py_2()
+            .when(self.compact, |div| div.px_1())
+            .when(!self.compact, |div| div.px_3().py_2())
             .bg(white())
             .border_1()
             .border_color(rgb(0xcccccc))
             .rounded_md()
             .when(is_focused, |div: Div| div.border_color(rgb(0x0066ff)))
-            .child(if content.is_empty() {
-                div().text_color(rgb(0x999999)).child(placeholder)
-            } else {
-                div().child(content)
-            })
+            .child(
+                div()
+                    .relative()
+                    .child(if content.is_empty() {
+                        div().text_color(rgb(0x999999)).child(placeholder)
+                    } else {
+                        div().child(StyledText::new(content).with_highlights(marked))
+                    })
+                    // Register the input handler over the text so IME windows are placed next to it
+                    .child(
+                        canvas(
+                            |_, _, _| {},
+                            move |bounds, _, window, cx| {
+                                window.handle_input(
+                                    &focus_handle,
+                                    ElementInputHandler::new(bounds, input),
+                                    cx,
+                                );
+                            },
+                        )
+                        .absolute()
+                        .size_full(),
+                    ),
+            )
     }
 }
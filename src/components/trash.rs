@@ -0,0 +1,318 @@
+use crate::components::file_ops;
+use chrono::{Local, NaiveDateTime};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// A file or directory moved to the trash, with enough to put it back
+#[derive(Clone, Debug)]
+pub struct TrashedItem {
+    pub original_path: PathBuf,
+    /// Where the item now lives, under the trash's `files` directory
+    trashed_path: PathBuf,
+    /// The `.trashinfo` file recording where the item came from
+    info_path: PathBuf,
+}
+
+/// The user's home trash, as described by the freedesktop.org trash specification
+fn home_trash_dir() -> std::io::Result<PathBuf> {
+    let data_dir = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| dirs::home_dir().map(|home| home.join(".local").join("share")))
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "No home directory"))?;
+    Ok(data_dir.join("Trash"))
+}
+
+/// The trash an item goes to: the home trash, or for an item on another filesystem, one
+/// at the top of that filesystem so the item can be moved there rather than copied
+fn trash_dir_for(path: &Path) -> std::io::Result<PathBuf> {
+    let home_trash = home_trash_dir()?;
+    #[cfg(unix)]
+    if let Some(trash) = topdir_trash_dir(path, &home_trash) {
+        return Ok(trash);
+    }
+    Ok(home_trash)
+}
+
+/// `$topdir/.Trash-$uid` for the filesystem holding `path`, if that isn't the one the home
+/// trash is on. None when it can't be created, leaving the item to be copied home.
+#[cfg(unix)]
+fn topdir_trash_dir(path: &Path, home_trash: &Path) -> Option<PathBuf> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+
+    let device = |path: &Path| {
+        path.ancestors()
+            .find_map(|dir| std::fs::metadata(dir).ok())
+            .map(|metadata| metadata.dev())
+    };
+    let item_device = device(path.parent()?)?;
+    if device(home_trash) == Some(item_device) {
+        return None;
+    }
+    // The highest directory still on the item's filesystem
+    let topdir = path
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| std::fs::metadata(dir).is_ok_and(|m| m.dev() == item_device))
+        .last()?;
+
+    // SAFETY: getuid has no preconditions and can't fail
+    let uid = unsafe { libc::getuid() };
+    let trash = topdir.join(format!(".Trash-{}", uid));
+    match std::fs::DirBuilder::new().mode(0o700).create(&trash) {
+        Ok(()) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {}
+        Err(_) => return None,
+    }
+    // Someone else's directory, or a link planted in its place, mustn't be used
+    let metadata = trash.symlink_metadata().ok()?;
+    (metadata.is_dir() && metadata.uid() == uid).then_some(trash)
+}
+
+/// Move a file or directory to the trash, recording where it came from so it can be
+/// restored from here or from a file manager
+pub fn move_to_trash(path: &Path) -> std::io::Result<TrashedItem> {
+    let original_path = std::path::absolute(path)?;
+    let trash = trash_dir_for(&original_path)?;
+    move_into_trash(&original_path, &trash)
+}
+
+fn move_into_trash(original_path: &Path, trash: &Path) -> std::io::Result<TrashedItem> {
+    let files_dir = trash.join("files");
+    let info_dir = trash.join("info");
+    std::fs::create_dir_all(&files_dir)?;
+    std::fs::create_dir_all(&info_dir)?;
+
+    let name = original_path
+        .file_name()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "No file name"))?
+        .to_string_lossy()
+        .into_owned();
+    let (trashed_name, info_path, mut info_file) = claim_name(&files_dir, &info_dir, &name)?;
+
+    let info = trash_info(original_path, Local::now().naive_local());
+    let trashed_path = files_dir.join(trashed_name);
+    let result = info_file
+        .write_all(info.as_bytes())
+        .and_then(|_| move_path(original_path, &trashed_path));
+    if let Err(err) = result {
+        // A copy that was made but couldn't replace the original stays restorable
+        if trashed_path.symlink_metadata().is_err() {
+            std::fs::remove_file(&info_path).ok();
+        }
+        return Err(err);
+    }
+
+    Ok(TrashedItem {
+        original_path: original_path.to_path_buf(),
+        trashed_path,
+        info_path,
+    })
+}
+
+/// Pick the name an item is trashed under: its own, or `name.2`, `name.3` and so on once
+/// that's taken. The name is claimed by creating its info file, which fails if another
+/// item already has it, and the open info file is returned.
+fn claim_name(
+    files_dir: &Path,
+    info_dir: &Path,
+    name: &str,
+) -> std::io::Result<(String, PathBuf, File)> {
+    let mut attempt = 1;
+    loop {
+        let trashed_name = if attempt == 1 {
+            name.to_string()
+        } else {
+            format!("{}.{}", name, attempt)
+        };
+        let info_path = info_dir.join(format!("{}.trashinfo", trashed_name));
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            Ok(file) if files_dir.join(&trashed_name).symlink_metadata().is_err() => {
+                return Ok((trashed_name, info_path, file));
+            }
+            Ok(_) => {
+                std::fs::remove_file(&info_path).ok();
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(err) => return Err(err),
+        }
+        attempt += 1;
+    }
+}
+
+/// Put a trashed item back where it was, unless something has taken its place
+pub fn restore(item: &TrashedItem) -> std::io::Result<()> {
+    if item.original_path.symlink_metadata().is_ok() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("{} already exists", item.original_path.display()),
+        ));
+    }
+    move_path(&item.trashed_path, &item.original_path)?;
+    std::fs::remove_file(&item.info_path).ok();
+    Ok(())
+}
+
+/// Rename a file or directory, or copy it and remove the original when it's going to
+/// another filesystem
+fn move_path(from: &Path, to: &Path) -> std::io::Result<()> {
+    match std::fs::rename(from, to) {
+        Err(err) if err.kind() == std::io::ErrorKind::CrossesDevices => copy_and_remove(from, to),
+        result => result,
+    }
+}
+
+fn copy_and_remove(from: &Path, to: &Path) -> std::io::Result<()> {
+    if let Err(err) = file_ops::copy_recursive(from, to) {
+        remove_path(to).ok();
+        return Err(err);
+    }
+    remove_path(from)
+}
+
+fn remove_path(path: &Path) -> std::io::Result<()> {
+    if path.symlink_metadata()?.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    }
+}
+
+/// The contents of a `.trashinfo` file for an item deleted at a local time
+fn trash_info(original_path: &Path, deleted_at: NaiveDateTime) -> String {
+    format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        percent_encode(original_path),
+        deleted_at.format("%Y-%m-%dT%H:%M:%S"),
+    )
+}
+
+/// Escape a path for a `.trashinfo` file, keeping separators and unreserved characters
+fn percent_encode(path: &Path) -> String {
+    let mut encoded = String::new();
+    for &byte in path.as_os_str().as_encoded_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::test_support::TempDir;
+
+    #[test]
+    fn test_trash_info() {
+        let deleted_at = chrono::NaiveDate::from_ymd_opt(2024, 3, 9)
+            .unwrap()
+            .and_hms_opt(7, 5, 1)
+            .unwrap();
+        assert_eq!(
+            trash_info(Path::new("/home/me/a file%.txt"), deleted_at),
+            "[Trash Info]\nPath=/home/me/a%20file%25.txt\nDeletionDate=2024-03-09T07:05:01\n"
+        );
+        assert_eq!(percent_encode(Path::new("/tmp/é")), "/tmp/%C3%A9");
+    }
+
+    #[test]
+    fn test_claim_name() {
+        let dir = TempDir::new("trash-claim");
+        let (files_dir, info_dir) = (dir.join("files"), dir.join("info"));
+        std::fs::create_dir(&files_dir).unwrap();
+        std::fs::create_dir(&info_dir).unwrap();
+
+        let (name, info_path, _) = claim_name(&files_dir, &info_dir, "a").unwrap();
+        assert_eq!(name, "a");
+        assert_eq!(info_path, info_dir.join("a.trashinfo"));
+        let (name, _, _) = claim_name(&files_dir, &info_dir, "a").unwrap();
+        assert_eq!(name, "a.2");
+
+        // A trashed file without an info file still holds its name
+        std::fs::write(files_dir.join("a.3"), "").unwrap();
+        let (name, _, _) = claim_name(&files_dir, &info_dir, "a").unwrap();
+        assert_eq!(name, "a.4");
+        assert!(!info_dir.join("a.3.trashinfo").exists());
+    }
+
+    #[test]
+    fn test_trash_and_restore() {
+        let dir = TempDir::new("trash-restore");
+        let trash = dir.join("Trash");
+        let original = dir.join("notes.txt");
+        std::fs::write(&original, "hello").unwrap();
+
+        let item = move_into_trash(&original, &trash).unwrap();
+        assert!(!original.exists());
+        assert_eq!(item.trashed_path, trash.join("files").join("notes.txt"));
+        let info = std::fs::read_to_string(&item.info_path).unwrap();
+        assert!(info.starts_with(&format!(
+            "[Trash Info]\nPath={}\n",
+            percent_encode(&original)
+        )));
+
+        // Restoring doesn't overwrite something created in the item's place
+        std::fs::write(&original, "new").unwrap();
+        assert!(restore(&item).is_err());
+        std::fs::remove_file(&original).unwrap();
+
+        restore(&item).unwrap();
+        assert_eq!(std::fs::read_to_string(&original).unwrap(), "hello");
+        assert!(!item.trashed_path.exists());
+        assert!(!item.info_path.exists());
+    }
+
+    #[test]
+    fn test_copy_and_remove() {
+        let dir = TempDir::new("trash-copy");
+        let from = dir.join("from");
+        std::fs::create_dir_all(from.join("sub")).unwrap();
+        std::fs::write(from.join("sub").join("file"), "contents").unwrap();
+
+        let to = dir.join("to");
+        copy_and_remove(&from, &to).unwrap();
+        assert!(!from.exists());
+        assert_eq!(
+            std::fs::read_to_string(to.join("sub").join("file")).unwrap(),
+            "contents"
+        );
+
+        // A target that can't be written leaves the original alone
+        let blocker = dir.join("blocker");
+        std::fs::write(&blocker, "").unwrap();
+        assert!(copy_and_remove(&to, &blocker.join("to")).is_err());
+        assert_eq!(
+            std::fs::read_to_string(to.join("sub").join("file")).unwrap(),
+            "contents"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_failed_copy_is_cleaned_up() {
+        let dir = TempDir::new("trash-copy-failure");
+        let from = dir.join("from");
+        std::fs::create_dir(&from).unwrap();
+        std::fs::write(from.join("file"), "contents").unwrap();
+        // Sockets can't be copied, so the copy fails partway through
+        let _listener = std::os::unix::net::UnixListener::bind(from.join("socket")).unwrap();
+
+        let to = dir.join("to");
+        assert!(copy_and_remove(&from, &to).is_err());
+        assert!(to.symlink_metadata().is_err());
+        assert_eq!(
+            std::fs::read_to_string(from.join("file")).unwrap(),
+            "contents"
+        );
+        assert!(from.join("socket").exists());
+    }
+}
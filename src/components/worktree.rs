@@ -1,92 +1,378 @@
+use crate::components::editor_element::{ScrollbarLayout, SCROLLBAR_WIDTH};
+use crate::components::entry_tree::{file_id, Entry, EntryId, EntryTree, FileId, LoadState};
+use crate::components::file_ops;
+use crate::components::git_status::GitStatuses;
+use crate::components::ignore_rules::{is_ignore_file, IgnoreStack, WorktreeIgnores};
+use crate::components::trash::{self, TrashedItem};
+use crate::components::{TextEditor, TextInput, TextInputEvent};
 use gpui::{prelude::*, *};
-use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use notify::event::ModifyKind;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::cell::Cell;
+use std::collections::{BTreeSet, HashSet};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
-use std::sync::mpsc::{channel, Receiver};
+use std::rc::Rc;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-#[derive(Clone, Debug)]
-pub enum EntryKind {
-    File,
-    Directory,
-}
+actions!(
+    worktree,
+    [
+        ToggleHiddenFiles,
+        ToggleFollowSymlinks,
+        NewFile,
+        NewFolder,
+        Rename,
+        Duplicate,
+        MoveToTrash,
+        UndoMoveToTrash,
+        CopyPath,
+        CopyRelativePath,
+        SelectNext,
+        SelectPrevious,
+        CollapseSelected,
+        ExpandSelected,
+        OpenSelected,
+        PreviewSelected,
+    ]
+);
+
+/// How long after the last keypress type-ahead starts a new search
+const TYPE_AHEAD_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long something dragged has to hover over a collapsed folder before it opens
+const DRAG_EXPAND_DELAY: Duration = Duration::from_millis(600);
+
+/// Every row in the tree is this tall, so only the rows in view need laying out
+const ROW_HEIGHT: Pixels = px(28.);
+
+/// How long events must stop arriving before they're applied, so a burst like a git
+/// checkout updates the tree once
+const DEBOUNCE_INTERVAL: Duration = Duration::from_millis(100);
+
+pub use crate::components::entry_tree::EntryKind;
 
+/// A file or directory in the worktree's flat index
 #[derive(Clone, Debug)]
-pub struct Entry {
+pub struct IndexEntry {
     pub path: PathBuf,
-    pub name: String,
     pub kind: EntryKind,
-    pub children: Vec<Entry>,
-    pub is_expanded: bool,
 }
 
-impl Entry {
-    pub fn from_path(path: impl AsRef<Path>) -> std::io::Result<Self> {
-        let path = path.as_ref();
-        let name = path
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("")
-            .to_string();
+/// List every file and directory under a path that isn't ignored, without building a tree.
+/// Symlinked directories are only followed when asked, and never into a directory already
+/// visited, so links that loop are listed but not walked.
+fn build_index(
+    root: &Path,
+    ignores: Arc<WorktreeIgnores>,
+    follow_symlinks: bool,
+) -> Vec<IndexEntry> {
+    let mut index = Vec::new();
+    let mut visited = HashSet::new();
+    if let Some(id) = std::fs::metadata(root).ok().and_then(|m| file_id(&m)) {
+        visited.insert(id);
+    }
+    let ignores = IgnoreStack::for_dir(ignores, root, root);
+    index_dir(root, ignores, follow_symlinks, &mut visited, &mut index);
+    index
+}
 
-        let metadata = std::fs::metadata(path)?;
+/// Bring an index up to date with changes to some paths, without walking the rest of the
+/// worktree. Each changed path is dropped along with everything below it, then listed
+/// again if it still exists and isn't ignored.
+fn update_index(
+    mut index: Vec<IndexEntry>,
+    root: &Path,
+    ignores: Arc<WorktreeIgnores>,
+    follow_symlinks: bool,
+    changed: &HashSet<PathBuf>,
+) -> Vec<IndexEntry> {
+    if changed.contains(root) {
+        return build_index(root, ignores, follow_symlinks);
+    }
+    index.retain(|entry| !entry.path.ancestors().any(|path| changed.contains(path)));
 
-        if metadata.is_dir() {
-            let mut children = Vec::new();
+    // Directories whose contents are indexed, which a new entry's parent has to be
+    let indexed_dirs = index
+        .iter()
+        .filter(|entry| match entry.kind {
+            EntryKind::Directory => true,
+            EntryKind::Symlink { is_dir, .. } => is_dir && follow_symlinks,
+            EntryKind::File => false,
+        })
+        .map(|entry| entry.path.clone())
+        .collect::<HashSet<_>>();
+    // Relisting a directory covers everything changed inside it
+    let relist = changed.iter().filter(|path| {
+        path.ancestors()
+            .skip(1)
+            .take_while(|ancestor| *ancestor != root)
+            .all(|ancestor| !changed.contains(ancestor))
+    });
+    for path in relist {
+        let Some(parent) = path.parent() else {
+            continue;
+        };
+        if parent != root && !indexed_dirs.contains(parent) {
+            continue;
+        }
+        let Ok(entry) = Entry::from_path(path) else {
+            continue;
+        };
+        let parent_ignores = IgnoreStack::for_dir(ignores.clone(), root, parent);
+        if parent_ignores.is_ignored(&entry.path, entry.is_dir()) {
+            continue;
+        }
+        // Only the directories above count as visited, so links can't loop back into them
+        let mut visited = path
+            .ancestors()
+            .skip(1)
+            .take_while(|ancestor| ancestor.starts_with(root))
+            .filter_map(|ancestor| std::fs::metadata(ancestor).ok().and_then(|m| file_id(&m)))
+            .collect::<HashSet<_>>();
+        let descend = entry.is_dir()
+            && (follow_symlinks || !entry.is_symlink())
+            && entry.file_id.is_none_or(|id| visited.insert(id));
+        if descend {
+            let ignores = parent_ignores.child(&entry.path);
+            index_dir(
+                &entry.path,
+                ignores,
+                follow_symlinks,
+                &mut visited,
+                &mut index,
+            );
+        }
+        index.push(IndexEntry {
+            path: entry.path,
+            kind: entry.kind,
+        });
+    }
+    index
+}
 
-            if let Ok(entries) = std::fs::read_dir(path) {
-                for entry in entries.flatten() {
-                    if let Ok(child) = Self::from_path(entry.path()) {
-                        children.push(child);
-                    }
-                }
+/// Add everything below a directory to an index, skipping directories already visited
+fn index_dir(
+    dir: &Path,
+    ignores: IgnoreStack,
+    follow_symlinks: bool,
+    visited: &mut HashSet<FileId>,
+    index: &mut Vec<IndexEntry>,
+) {
+    let mut dirs = vec![(dir.to_path_buf(), ignores)];
+    while let Some((dir, ignores)) = dirs.pop() {
+        let Ok(read_dir) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for dir_entry in read_dir.flatten() {
+            let Ok(entry) = Entry::from_path(dir_entry.path()) else {
+                continue;
+            };
+            if ignores.is_ignored(&entry.path, entry.is_dir()) {
+                continue;
             }
-
-            // Sort: directories first, then files, alphabetically
-            children.sort_by(|a, b| match (&a.kind, &b.kind) {
-                (EntryKind::Directory, EntryKind::File) => std::cmp::Ordering::Less,
-                (EntryKind::File, EntryKind::Directory) => std::cmp::Ordering::Greater,
-                _ => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            let descend = entry.is_dir()
+                && (follow_symlinks || !entry.is_symlink())
+                && entry.file_id.is_none_or(|id| visited.insert(id));
+            if descend {
+                dirs.push((entry.path.clone(), ignores.child(&entry.path)));
+            }
+            index.push(IndexEntry {
+                path: entry.path,
+                kind: entry.kind,
             });
+        }
+    }
+}
 
-            Ok(Self {
-                path: path.to_path_buf(),
-                name,
-                kind: EntryKind::Directory,
-                children,
-                is_expanded: false,
-            })
-        } else {
-            Ok(Self {
-                path: path.to_path_buf(),
-                name,
-                kind: EntryKind::File,
-                children: Vec::new(),
-                is_expanded: false,
-            })
+/// Whether a change to a path within the worktree can change what git reports. Inside
+/// `.git/`, objects, reflogs and lock files churn as git works, but they only matter once
+/// the index, HEAD, a ref or the excludes they lead up to are rewritten.
+fn affects_git_status(path: &Path) -> bool {
+    let mut git_path = path.iter().skip_while(|part| *part != ".git").skip(1);
+    match git_path.next() {
+        // Outside `.git/`, or the repository itself appearing or going
+        None => true,
+        Some(name) if name == "objects" || name == "logs" => false,
+        Some(_) => !path
+            .extension()
+            .is_some_and(|extension| extension == "lock"),
+    }
+}
+
+fn is_content_change(kind: &EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Access(_) | EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Metadata(_))
+    )
+}
+
+/// The filesystem changes from a burst of watcher events
+#[derive(Default)]
+struct EventBatch {
+    /// Paths whose entries were created, removed or renamed
+    paths: HashSet<PathBuf>,
+    /// Files whose contents were written, which leaves the tree as it is
+    modified: HashSet<PathBuf>,
+    /// Set when an event asks for the whole tree to be scanned again
+    needs_rescan: bool,
+}
+
+/// Block until the watcher sends events, then gather them until they stop arriving for a
+/// moment. Returns None once the watcher is gone.
+fn next_event_batch(receiver: &Receiver<notify::Result<Event>>) -> Option<EventBatch> {
+    let mut batch = EventBatch::default();
+    let mut event = receiver.recv().ok()?;
+    loop {
+        match event {
+            // Writes to a file's contents don't change the tree
+            Ok(event) if is_content_change(&event.kind) => batch.modified.extend(event.paths),
+            Ok(event) if !event.need_rescan() => batch.paths.extend(event.paths),
+            _ => batch.needs_rescan = true,
         }
+        event = match receiver.recv_timeout(DEBOUNCE_INTERVAL) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => return Some(batch),
+            Err(RecvTimeoutError::Disconnected) => return None,
+        };
     }
 }
 
-use crate::components::TextEditor;
+/// An entry being dragged out of the tree
+#[derive(Clone, Debug)]
+pub struct DraggedEntry {
+    pub path: PathBuf,
+    name: SharedString,
+}
+
+impl Render for DraggedEntry {
+    fn render(&mut self, _window: &mut Window, _cx: &mut Context<Self>) -> impl IntoElement {
+        div()
+            .px_2()
+            .py_1()
+            .text_sm()
+            .bg(white())
+            .border_1()
+            .border_color(rgb(0xcccccc))
+            .rounded_md()
+            .child(self.name.clone())
+    }
+}
+
+/// What the name being typed into the tree is for
+#[derive(Clone, Debug)]
+enum NameEdit {
+    NewFile { dir: PathBuf },
+    NewFolder { dir: PathBuf },
+    Rename { path: PathBuf },
+}
+
+/// A line in the tree as drawn
+#[derive(Clone, Copy, Debug)]
+enum Row {
+    Entry {
+        id: EntryId,
+        depth: usize,
+    },
+    /// Where the name of a new file or folder is typed, at the top of its directory
+    NewEntry {
+        depth: usize,
+    },
+}
+
+/// Where the entry list was scrolled when it was last laid out
+#[derive(Clone, Copy, Default)]
+struct ListScroll {
+    viewport: Bounds<Pixels>,
+    scroll_y: Pixels,
+    content_height: Pixels,
+}
+
+/// Records the entry list's scroll position as the list lays itself out, for the scrollbar
+#[derive(Clone, Default)]
+struct ScrollTracker(Rc<Cell<ListScroll>>);
+
+impl UniformListDecoration for ScrollTracker {
+    fn compute(
+        &self,
+        _visible_range: Range<usize>,
+        bounds: Bounds<Pixels>,
+        scroll_offset: Point<Pixels>,
+        item_height: Pixels,
+        item_count: usize,
+        _window: &mut Window,
+        _cx: &mut App,
+    ) -> AnyElement {
+        // The bounds given move with the content, so take the scroll back off for the viewport
+        self.0.set(ListScroll {
+            viewport: Bounds::new(bounds.origin - scroll_offset, bounds.size),
+            scroll_y: -scroll_offset.y,
+            content_height: item_height * item_count,
+        });
+        Empty.into_any_element()
+    }
+}
+
+/// An inline input for naming a new or renamed entry
+struct NameEditor {
+    edit: NameEdit,
+    input: Entity<TextInput>,
+    _subscription: Subscription,
+}
 
 pub struct Worktree {
     focus_handle: FocusHandle,
-    root: Entry,
-    #[allow(dead_code)]
+    entries: EntryTree,
+    /// What's drawn, top to bottom, worked out from which directories are expanded
+    rows: Vec<Row>,
+    scroll_handle: UniformListScrollHandle,
+    list_scroll: ScrollTracker,
+    /// Distance from the top of the scrollbar thumb to where it was grabbed, while dragging it
+    scrollbar_drag: Option<Pixels>,
     root_path: PathBuf,
-    #[allow(dead_code)]
+    /// The root with symlinks resolved, as some platforms report event paths
+    canonical_root: PathBuf,
+    /// The entry the cursor is on, which keyboard navigation moves and file operations act on
+    selected: Option<PathBuf>,
+    /// Other entries selected along with `selected`, by shift- or ctrl-clicking
+    marked: BTreeSet<PathBuf>,
+    /// Where a shift-click range starts
+    anchor: Option<PathBuf>,
+    /// The name prefix typed so far to jump to an entry
+    type_ahead: String,
+    last_typed_at: Option<Instant>,
+    name_editor: Option<NameEditor>,
+    /// Entries moved to the trash together, most recent last, so they can be put back
+    trashed: Vec<Vec<TrashedItem>>,
+    /// Why the last file operation failed
+    error: Option<String>,
+    /// The collapsed folder something is being dragged over
+    drag_hover: Option<PathBuf>,
+    _drag_expand_task: Task<()>,
     _watcher: Option<RecommendedWatcher>,
-    #[allow(dead_code)]
-    _receiver: Option<Receiver<notify::Result<Event>>>,
     editor: Option<Entity<TextEditor>>,
+    /// Ignore rules that don't come from a particular directory
+    ignores: Arc<WorktreeIgnores>,
+    /// Whether ignored entries and dotfiles are listed
+    show_hidden: bool,
+    /// Whether symlinked directories can be expanded and are indexed
+    follow_symlinks: bool,
+    /// Every path in the worktree that isn't ignored, for finding files without walking the
+    /// tree
+    index: Vec<IndexEntry>,
+    _index_task: Task<()>,
+    /// What git reports for the files in the worktree, shared with the editor
+    git_statuses: Arc<GitStatuses>,
+    _git_task: Task<()>,
+    _watch_task: Task<()>,
 }
 
 impl Worktree {
     pub fn new(path: impl AsRef<Path>, cx: &mut Context<Self>) -> std::io::Result<Self> {
         let path = path.as_ref();
-        let mut root = Entry::from_path(path)?;
-
-        // Expand only the root directory by default
-        root.is_expanded = true;
+        let root = Entry::from_path(path)?;
 
         // Set up filesystem watcher
         let (tx, rx) = channel();
@@ -96,71 +382,1123 @@ impl Worktree {
             let _ = w.watch(path, RecursiveMode::Recursive);
         }
 
-        Ok(Self {
+        // The watcher sends events from its own thread. Wait for them on a background
+        // thread, so the worktree only wakes once a batch of them has settled.
+        let watch_task = cx.spawn(async move |this, cx| {
+            let mut receiver = rx;
+            loop {
+                let (returned, batch) = cx
+                    .background_executor()
+                    .spawn(async move {
+                        let batch = next_event_batch(&receiver);
+                        (receiver, batch)
+                    })
+                    .await;
+                receiver = returned;
+                // The watcher is dropped along with the worktree
+                let Some(batch) = batch else {
+                    break;
+                };
+                if this
+                    .update(cx, |this, cx| this.apply_events(batch, cx))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        let mut worktree = Self {
             focus_handle: cx.focus_handle(),
-            root,
+            entries: EntryTree::new(root),
+            rows: Vec::new(),
+            scroll_handle: UniformListScrollHandle::new(),
+            list_scroll: ScrollTracker::default(),
+            scrollbar_drag: None,
             root_path: path.to_path_buf(),
+            canonical_root: path.canonicalize().unwrap_or_else(|_| path.to_path_buf()),
+            selected: None,
+            marked: BTreeSet::new(),
+            anchor: None,
+            type_ahead: String::new(),
+            last_typed_at: None,
+            name_editor: None,
+            trashed: Vec::new(),
+            error: None,
+            drag_hover: None,
+            _drag_expand_task: Task::ready(()),
             _watcher: watcher,
-            _receiver: Some(rx),
             editor: None,
+            ignores: Arc::new(WorktreeIgnores::new(path, &[])),
+            show_hidden: false,
+            follow_symlinks: true,
+            index: Vec::new(),
+            _index_task: Task::ready(()),
+            git_statuses: Arc::new(GitStatuses::default()),
+            _git_task: Task::ready(()),
+            _watch_task: watch_task,
+        };
+
+        // Expand only the root directory by default
+        worktree.toggle_entry(&path.to_path_buf(), cx);
+        worktree.update_rows();
+        worktree.rebuild_index(cx);
+        worktree.reload_git_statuses(cx);
+        Ok(worktree)
+    }
+
+    /// Read a directory's children on the background executor
+    fn load_children(&mut self, path: PathBuf, cx: &mut Context<Self>) {
+        let Some(id) = self.entries.id_for_path(&path) else {
+            return;
+        };
+        let ancestor_ids = self.entries.ancestor_ids(id);
+        let Some(entry) = self.entries.get_mut(id) else {
+            return;
+        };
+        if entry.load_state != LoadState::NotLoaded {
+            return;
+        }
+        if entry.is_symlink() && !self.follow_symlinks {
+            return;
+        }
+        if entry.file_id.is_some_and(|id| ancestor_ids.contains(&id)) {
+            entry.load_state = LoadState::Cycle;
+            return;
+        }
+        entry.load_state = LoadState::Loading;
+        let parent_ignored = entry.is_ignored;
+        let ignores = self.ignores.clone();
+        let root = self.root_path.clone();
+
+        cx.spawn(async move |this, cx| {
+            let dir = path.clone();
+            let children = cx
+                .background_executor()
+                .spawn(async move {
+                    let ignores = IgnoreStack::for_dir(ignores, &root, &dir);
+                    Entry::read_children(&dir, &ignores, parent_ignored)
+                })
+                .await;
+            this.update(cx, |this, cx| {
+                // Look the directory up again, as it may have gone while it was read
+                let Some(id) = this.entries.id_for_path(&path) else {
+                    return;
+                };
+                this.entries.set_children(id, children.unwrap_or_default());
+                if let Some(entry) = this.entries.get_mut(id) {
+                    entry.load_state = LoadState::Loaded;
+                }
+                this.update_rows();
+                cx.notify();
+            })
+            .ok();
         })
+        .detach();
+    }
+
+    /// Walk the whole worktree on the background executor to rebuild the flat index
+    fn rebuild_index(&mut self, cx: &mut Context<Self>) {
+        let root = self.root_path.clone();
+        let ignores = self.ignores.clone();
+        let follow_symlinks = self.follow_symlinks;
+        self._index_task = cx.spawn(async move |this, cx| {
+            let index = cx
+                .background_executor()
+                .spawn(async move { build_index(&root, ignores, follow_symlinks) })
+                .await;
+            this.update(cx, |this, _cx| this.index = index).ok();
+        });
+    }
+
+    /// Update the flat index for changed paths on the background executor, after any scan
+    /// that's under way
+    fn update_index(&mut self, changed: HashSet<PathBuf>, cx: &mut Context<Self>) {
+        let root = self.root_path.clone();
+        let ignores = self.ignores.clone();
+        let follow_symlinks = self.follow_symlinks;
+        let previous = std::mem::replace(&mut self._index_task, Task::ready(()));
+        self._index_task = cx.spawn(async move |this, cx| {
+            previous.await;
+            let Ok(index) = this.update(cx, |this, _cx| std::mem::take(&mut this.index)) else {
+                return;
+            };
+            let index = cx
+                .background_executor()
+                .spawn(
+                    async move { update_index(index, &root, ignores, follow_symlinks, &changed) },
+                )
+                .await;
+            this.update(cx, |this, _cx| this.index = index).ok();
+        });
+    }
+
+    /// Read the repository's status on the background executor, then pass it to the editor
+    fn reload_git_statuses(&mut self, cx: &mut Context<Self>) {
+        let root = self.root_path.clone();
+        self._git_task = cx.spawn(async move |this, cx| {
+            let statuses = cx
+                .background_executor()
+                .spawn(async move { GitStatuses::load(&root) })
+                .await;
+            this.update(cx, |this, cx| {
+                this.git_statuses = Arc::new(statuses);
+                if let Some(editor) = &this.editor {
+                    let statuses = this.git_statuses.clone();
+                    editor.update(cx, |editor, cx| editor.set_git_statuses(statuses, cx));
+                }
+                cx.notify();
+            })
+            .ok();
+        });
+    }
+
+    /// Every file and directory in the worktree, as of the last scan
+    #[allow(dead_code)]
+    pub fn index(&self) -> &[IndexEntry] {
+        &self.index
+    }
+
+    /// Patch the directories a settled batch of filesystem events touched. Read git's
+    /// status again if it could have changed, and let the editor check its file if the
+    /// batch touched it.
+    fn apply_events(&mut self, batch: EventBatch, cx: &mut Context<Self>) {
+        let changed = || batch.paths.iter().chain(&batch.modified);
+        let git_changed = batch.needs_rescan
+            || changed().any(|path| self.relative_path(path).is_some_and(affects_git_status));
+        let open_file = self
+            .editor
+            .as_ref()
+            .and_then(|editor| editor.read(cx).buffer().file_path())
+            .map(Path::to_path_buf);
+        // Renaming or removing a directory affects the files inside it
+        let open_file_changed = open_file.is_some_and(|open_file| {
+            batch.needs_rescan
+                || changed()
+                    .filter_map(|path| self.tree_path(path))
+                    .any(|path| open_file.starts_with(path))
+        });
+
+        if batch.needs_rescan {
+            self.refresh(cx);
+        } else {
+            // Rewriting ignore rules changes which entries are shown
+            let mut paths = batch.paths;
+            paths.extend(
+                batch
+                    .modified
+                    .into_iter()
+                    .filter(|path| is_ignore_file(path)),
+            );
+            self.apply_changes(paths, cx);
+        }
+        if git_changed {
+            self.reload_git_statuses(cx);
+        }
+        if let Some(editor) = self.editor.as_ref().filter(|_| open_file_changed) {
+            editor.update(cx, |editor, cx| editor.check_disk(cx));
+        }
+    }
+
+    /// Rescan the directories containing changed paths. Creating, deleting or renaming an
+    /// entry changes its parent's listing, so only those listings are read again. A changed
+    /// ignore file can affect everything below it, so that whole subtree is rechecked, and
+    /// a changed `.git/info/exclude` the whole tree.
+    fn apply_changes(&mut self, paths: HashSet<PathBuf>, cx: &mut Context<Self>) {
+        let mut dirs = HashSet::new();
+        let mut ignore_changed = HashSet::new();
+        let mut changed = HashSet::new();
+        for path in paths.iter().filter_map(|path| self.tree_path(path)) {
+            let Some(dir) = path.parent() else {
+                continue;
+            };
+            if self.ignores.invalidate(&path) {
+                ignore_changed.insert(self.root_path.clone());
+                dirs.insert(self.root_path.clone());
+                changed.insert(self.root_path.clone());
+            }
+            if is_ignore_file(&path) {
+                ignore_changed.insert(dir.to_path_buf());
+                changed.insert(dir.to_path_buf());
+            }
+            dirs.insert(dir.to_path_buf());
+            changed.insert(path);
+        }
+        if dirs.is_empty() {
+            return;
+        }
+        for dir in &dirs {
+            let ignores = IgnoreStack::for_dir(self.ignores.clone(), &self.root_path, dir);
+            if let Some(id) = self.entries.id_for_path(dir) {
+                if ignore_changed.contains(dir) {
+                    self.entries.rescan_loaded(id, &ignores);
+                } else {
+                    self.entries.rescan_children(id, &ignores);
+                }
+            }
+        }
+        self.update_rows();
+        self.clear_missing_selection();
+        self.update_index(changed, cx);
+        cx.notify();
+    }
+
+    /// Map a path from a filesystem event to where it is within the worktree
+    fn relative_path<'a>(&self, path: &'a Path) -> Option<&'a Path> {
+        path.strip_prefix(&self.root_path)
+            .or_else(|_| path.strip_prefix(&self.canonical_root))
+            .ok()
+    }
+
+    /// Map a path from a filesystem event to how the tree spells it
+    fn tree_path(&self, path: &Path) -> Option<PathBuf> {
+        let relative = self.relative_path(path)?;
+        if relative.as_os_str().is_empty() {
+            return None;
+        }
+        Some(self.root_path.join(relative))
+    }
+
+    fn clear_missing_selection(&mut self) {
+        if let Some(selected) = &self.selected {
+            if self.entries.id_for_path(selected).is_none() {
+                self.selected = None;
+            }
+        }
+        let entries = &self.entries;
+        self.marked
+            .retain(|path| entries.id_for_path(path).is_some());
+        if self.selected.is_none() {
+            self.selected = self.marked.pop_last();
+        }
+    }
+
+    /// Work out the rows to draw again, after entries are expanded, collapsed, added or removed
+    fn update_rows(&mut self) {
+        let creating_in =
+            self.name_editor
+                .as_ref()
+                .and_then(|name_editor| match &name_editor.edit {
+                    NameEdit::NewFile { dir } | NameEdit::NewFolder { dir } => {
+                        self.entries.id_for_path(dir)
+                    }
+                    NameEdit::Rename { .. } => None,
+                });
+        self.rows.clear();
+        for (id, depth) in self.entries.visible_entries(self.show_hidden) {
+            self.rows.push(Row::Entry { id, depth });
+            let is_expanded = self.entries.get(id).is_some_and(|entry| entry.is_expanded);
+            if creating_in == Some(id) && is_expanded {
+                self.rows.push(Row::NewEntry { depth: depth + 1 });
+            }
+        }
+    }
+
+    /// Every entry shown in the tree, top to bottom
+    fn visible_paths(&self) -> Vec<PathBuf> {
+        self.rows
+            .iter()
+            .filter_map(|row| match row {
+                Row::Entry { id, .. } => self.entries.get(*id).map(|entry| entry.path.clone()),
+                Row::NewEntry { .. } => None,
+            })
+            .collect()
+    }
+
+    /// Scroll the list so the cursor is in view
+    fn scroll_to_selected(&self) {
+        let Some(selected) = &self.selected else {
+            return;
+        };
+        let ix = self.rows.iter().position(|row| match row {
+            Row::Entry { id, .. } => self
+                .entries
+                .get(*id)
+                .is_some_and(|entry| entry.path == *selected),
+            Row::NewEntry { .. } => false,
+        });
+        if let Some(ix) = ix {
+            self.scroll_handle
+                .scroll_to_item(ix, ScrollStrategy::Center);
+        }
+    }
+
+    fn is_selected(&self, path: &Path) -> bool {
+        self.selected.as_deref() == Some(path) || self.marked.contains(path)
+    }
+
+    /// The selected entries in tree order, leaving out the root and anything inside another
+    /// selected folder
+    fn selected_paths(&self) -> Vec<PathBuf> {
+        let selected = self
+            .visible_paths()
+            .into_iter()
+            .filter(|path| *path != self.root_path && self.is_selected(path))
+            .collect::<Vec<_>>();
+        selected
+            .iter()
+            .filter(|path| {
+                !selected
+                    .iter()
+                    .any(|other| other != *path && path.starts_with(other))
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Select just this entry
+    fn select(&mut self, path: PathBuf) {
+        self.marked.clear();
+        self.anchor = Some(path.clone());
+        self.selected = Some(path);
+    }
+
+    /// Select every visible entry between the anchor and this one
+    fn select_range(&mut self, path: &Path) {
+        let visible = self.visible_paths();
+        let anchor = self.anchor.as_ref().or(self.selected.as_ref());
+        let anchor_ix = anchor.and_then(|anchor| visible.iter().position(|p| p == anchor));
+        let ix = visible.iter().position(|p| p == path);
+        let (Some(anchor_ix), Some(ix)) = (anchor_ix, ix) else {
+            self.select(path.to_path_buf());
+            return;
+        };
+        self.marked = visible[anchor_ix.min(ix)..=anchor_ix.max(ix)]
+            .iter()
+            .cloned()
+            .collect();
+        self.marked.remove(path);
+        self.selected = Some(path.to_path_buf());
+    }
+
+    /// Add an entry to the selection, or take it out
+    fn toggle_selected(&mut self, path: &Path) {
+        if self.selected.as_deref() == Some(path) {
+            self.selected = self.marked.pop_last();
+        } else if !self.marked.remove(path) {
+            if let Some(selected) = self.selected.take() {
+                self.marked.insert(selected);
+            }
+            self.selected = Some(path.to_path_buf());
+        }
+        self.anchor = Some(path.to_path_buf());
+    }
+
+    /// Expand or collapse a directory, or open a file in the editor. Returns whether a file
+    /// was opened.
+    fn activate(&mut self, path: &Path, cx: &mut Context<Self>) -> bool {
+        let Some(entry) = self.entries.entry_for_path(path) else {
+            return false;
+        };
+        let is_file = entry.is_file();
+        if entry.can_expand(self.follow_symlinks) {
+            self.toggle_entry(&path.to_path_buf(), cx);
+            false
+        } else if is_file {
+            self.open_file(path.to_path_buf(), cx);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn root_path(&self) -> &Path {
+        &self.root_path
     }
 
     pub fn set_editor(&mut self, editor: Entity<TextEditor>) {
         self.editor = Some(editor);
     }
 
-    #[allow(dead_code)]
-    pub fn refresh(&mut self, cx: &mut Context<Self>) {
-        if let Ok(new_root) = Entry::from_path(&self.root_path) {
-            // Preserve expansion state
-            self.preserve_expansion_state(&mut self.root.clone(), &new_root);
-            self.root = new_root;
-            cx.notify();
+    /// Hide paths matching these gitignore-style globs, on top of the project's ignore files
+    pub fn set_exclude_globs(&mut self, globs: &[String], cx: &mut Context<Self>) {
+        self.ignores = Arc::new(WorktreeIgnores::new(&self.root_path, globs));
+        self.refresh(cx);
+    }
+
+    /// Choose whether symlinked directories can be expanded and are included in the index
+    pub fn set_follow_symlinks(&mut self, follow: bool, cx: &mut Context<Self>) {
+        self.follow_symlinks = follow;
+        if !follow {
+            // Fold away what was shown through links
+            for (id, _) in self.entries.visible_entries(true) {
+                if let Some(entry) = self.entries.get_mut(id).filter(|e| e.is_symlink()) {
+                    entry.is_expanded = false;
+                }
+            }
+            self.update_rows();
         }
+        self.rebuild_index(cx);
+        cx.notify();
     }
 
-    #[allow(dead_code)]
-    fn preserve_expansion_state(&self, old_entry: &mut Entry, new_entry: &Entry) {
-        if old_entry.path == new_entry.path && old_entry.is_expanded {
-            // This path was expanded, keep it expanded
-            for new_child in &new_entry.children {
-                if let Some(old_child) = old_entry
-                    .children
-                    .iter_mut()
-                    .find(|c| c.path == new_child.path)
-                {
-                    self.preserve_expansion_state(old_child, new_child);
+    /// The directory new entries go in: the selected directory, or the selected file's
+    fn target_dir(&self) -> PathBuf {
+        match &self.selected {
+            Some(path) if path.is_dir() => path.clone(),
+            Some(path) => path
+                .parent()
+                .map_or_else(|| self.root_path.clone(), Path::to_path_buf),
+            None => self.root_path.clone(),
+        }
+    }
+
+    fn new_file(&mut self, _: &NewFile, window: &mut Window, cx: &mut Context<Self>) {
+        let dir = self.target_dir();
+        self.start_name_edit(NameEdit::NewFile { dir }, "", window, cx);
+    }
+
+    fn new_folder(&mut self, _: &NewFolder, window: &mut Window, cx: &mut Context<Self>) {
+        let dir = self.target_dir();
+        self.start_name_edit(NameEdit::NewFolder { dir }, "", window, cx);
+    }
+
+    fn rename(&mut self, _: &Rename, window: &mut Window, cx: &mut Context<Self>) {
+        let Some(path) = self.selected.clone().filter(|path| *path != self.root_path) else {
+            return;
+        };
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        self.start_name_edit(NameEdit::Rename { path }, &name, window, cx);
+    }
+
+    /// Show an input in the tree for naming an entry
+    fn start_name_edit(
+        &mut self,
+        edit: NameEdit,
+        initial: &str,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        // New entries appear inside their directory, so it has to be open
+        if let NameEdit::NewFile { dir } | NameEdit::NewFolder { dir } = &edit {
+            let is_expanded = self
+                .entries
+                .entry_for_path(dir)
+                .is_some_and(|entry| entry.is_expanded);
+            if !is_expanded {
+                self.toggle_entry(&dir.clone(), cx);
+            }
+        }
+
+        let placeholder = match &edit {
+            NameEdit::NewFile { .. } => "File name",
+            NameEdit::NewFolder { .. } => "Folder name",
+            NameEdit::Rename { .. } => "New name",
+        };
+        let input = cx.new(|cx| {
+            let mut input = TextInput::new(cx).placeholder(placeholder).compact();
+            input.set_content(initial, cx);
+            input
+        });
+        let subscription = cx.subscribe(&input, |this, _input, event, cx| match event {
+            TextInputEvent::Confirm => this.confirm_name_edit(cx),
+            TextInputEvent::Cancel => this.cancel_name_edit(cx),
+        });
+        let focus_handle = input.read(cx).focus_handle(cx);
+        window.focus(&focus_handle, cx);
+
+        self.name_editor = Some(NameEditor {
+            edit,
+            input,
+            _subscription: subscription,
+        });
+        self.error = None;
+        self.update_rows();
+        cx.notify();
+    }
+
+    fn cancel_name_edit(&mut self, cx: &mut Context<Self>) {
+        self.name_editor = None;
+        self.update_rows();
+        cx.notify();
+    }
+
+    /// Create or rename the entry with the typed name
+    fn confirm_name_edit(&mut self, cx: &mut Context<Self>) {
+        let Some(name_editor) = self.name_editor.take() else {
+            return;
+        };
+        self.update_rows();
+        let name = name_editor.input.read(cx).content().trim().to_string();
+        let result = file_ops::validate_name(&name).and_then(|_| match &name_editor.edit {
+            NameEdit::NewFile { dir } => {
+                let path = dir.join(&name);
+                file_ops::create_file(&path).map(|_| (None, path))
+            }
+            NameEdit::NewFolder { dir } => {
+                let path = dir.join(&name);
+                std::fs::create_dir(&path).map(|_| (None, path))
+            }
+            NameEdit::Rename { path } => {
+                let new_path = path.with_file_name(&name);
+                file_ops::rename(path, &new_path).map(|_| (Some(path.clone()), new_path))
+            }
+        });
+
+        match result {
+            Ok((old_path, new_path)) => {
+                if let Some(old_path) = &old_path {
+                    if let Some(editor) = &self.editor {
+                        editor.update(cx, |editor, cx| editor.file_moved(old_path, &new_path, cx));
+                    }
+                }
+                self.paths_changed(old_path.into_iter().chain([new_path.clone()]), cx);
+                self.select(new_path.clone());
+                if let NameEdit::NewFile { .. } = name_editor.edit {
+                    self.open_file(new_path, cx);
                 }
             }
+            Err(err) => self.error = Some(err.to_string()),
         }
+        cx.notify();
     }
 
-    fn toggle_entry(&mut self, path: &PathBuf, cx: &mut Context<Self>) {
-        Self::toggle_entry_recursive(&mut self.root, path);
+    fn duplicate(&mut self, _: &Duplicate, _window: &mut Window, cx: &mut Context<Self>) {
+        let Some(path) = self.selected.clone().filter(|path| *path != self.root_path) else {
+            return;
+        };
+        match file_ops::duplicate(&path) {
+            Ok(copy) => {
+                self.paths_changed([copy.clone()], cx);
+                self.select(copy);
+                self.error = None;
+            }
+            Err(err) => self.error = Some(err.to_string()),
+        }
+        cx.notify();
+    }
+
+    /// Move the selected entries to the trash
+    fn move_to_trash(&mut self, _: &MoveToTrash, _window: &mut Window, cx: &mut Context<Self>) {
+        let paths = self.selected_paths();
+        if paths.is_empty() {
+            return;
+        }
+        self.error = None;
+        let mut items = Vec::new();
+        let mut trashed_paths = Vec::new();
+        for path in paths {
+            match trash::move_to_trash(&path) {
+                Ok(item) => {
+                    items.push(item);
+                    trashed_paths.push(path);
+                }
+                Err(err) => self.error = Some(err.to_string()),
+            }
+        }
+        if !items.is_empty() {
+            self.trashed.push(items);
+        }
+        if let Some(editor) = &self.editor {
+            editor.update(cx, |editor, cx| {
+                for path in &trashed_paths {
+                    editor.file_deleted(path, cx);
+                }
+            });
+        }
+        self.paths_changed(trashed_paths, cx);
         cx.notify();
     }
 
-    fn toggle_entry_recursive(entry: &mut Entry, path: &PathBuf) -> bool {
-        if &entry.path == path {
-            entry.is_expanded = !entry.is_expanded;
-            return true;
+    /// Put back the entries most recently moved to the trash
+    fn undo_move_to_trash(
+        &mut self,
+        _: &UndoMoveToTrash,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let Some(items) = self.trashed.pop() else {
+            return;
+        };
+        self.error = None;
+        let mut restored = Vec::new();
+        let mut failed = Vec::new();
+        for item in items {
+            match trash::restore(&item) {
+                Ok(()) => restored.push(
+                    self.tree_path(&item.original_path)
+                        .unwrap_or(item.original_path),
+                ),
+                Err(err) => {
+                    self.error = Some(err.to_string());
+                    failed.push(item);
+                }
+            }
+        }
+        // Whatever couldn't be put back can be tried again
+        if !failed.is_empty() {
+            self.trashed.push(failed);
+        }
+        // The open file may be back where the editor expects it
+        if let Some(editor) = self.editor.as_ref().filter(|_| !restored.is_empty()) {
+            editor.update(cx, |editor, cx| editor.check_disk(cx));
+        }
+        self.paths_changed(restored.iter().cloned(), cx);
+        if let Some((last, rest)) = restored.split_last() {
+            self.select(last.clone());
+            self.marked.extend(rest.iter().cloned());
+        }
+        cx.notify();
+    }
+
+    fn copy_path(&mut self, _: &CopyPath, _window: &mut Window, cx: &mut Context<Self>) {
+        if let Some(path) = &self.selected {
+            let path = std::path::absolute(path).unwrap_or_else(|_| path.clone());
+            cx.write_to_clipboard(ClipboardItem::new_string(path.display().to_string()));
+        }
+    }
+
+    fn copy_relative_path(
+        &mut self,
+        _: &CopyRelativePath,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        if let Some(path) = &self.selected {
+            let relative = path.strip_prefix(&self.root_path).unwrap_or(path);
+            cx.write_to_clipboard(ClipboardItem::new_string(relative.display().to_string()));
+        }
+    }
+
+    /// Update the tree for changes made from here, without waiting for the watcher
+    fn paths_changed(&mut self, paths: impl IntoIterator<Item = PathBuf>, cx: &mut Context<Self>) {
+        self.apply_changes(paths.into_iter().collect(), cx);
+    }
+
+    fn open_file(&mut self, path: PathBuf, cx: &mut Context<Self>) {
+        if let Some(editor) = &self.editor {
+            editor.update(cx, |editor, cx| editor.open_file(path, cx));
         }
+    }
+
+    /// Open a collapsed folder once something has been dragged over it for a moment
+    fn drag_moved(
+        &mut self,
+        path: &Path,
+        bounds: Bounds<Pixels>,
+        position: Point<Pixels>,
+        cx: &mut Context<Self>,
+    ) {
+        let is_over = bounds.contains(&position);
+        let was_over = self.drag_hover.as_deref() == Some(path);
+        if is_over && !was_over {
+            let path = path.to_path_buf();
+            self.drag_hover = Some(path.clone());
+            self._drag_expand_task = cx.spawn(async move |this, cx| {
+                cx.background_executor().timer(DRAG_EXPAND_DELAY).await;
+                this.update(cx, |this, cx| {
+                    let still_over = this.drag_hover.as_ref() == Some(&path);
+                    let is_collapsed = this
+                        .entries
+                        .entry_for_path(&path)
+                        .is_some_and(|entry| !entry.is_expanded);
+                    if still_over && is_collapsed {
+                        this.toggle_entry(&path, cx);
+                    }
+                })
+                .ok();
+            });
+        } else if !is_over && was_over {
+            self.drag_hover = None;
+            self._drag_expand_task = Task::ready(());
+        }
+    }
 
-        for child in &mut entry.children {
-            if Self::toggle_entry_recursive(child, path) {
-                return true;
+    /// Move dragged entries into a directory, or copy them when alt is held
+    fn drop_entry(
+        &mut self,
+        dragged: &DraggedEntry,
+        dir: &Path,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.drag_hover = None;
+        self.error = None;
+        let copy = window.modifiers().alt;
+        let paths = if self.is_selected(&dragged.path) {
+            self.selected_paths()
+        } else {
+            vec![dragged.path.clone()]
+        };
+
+        let mut changed = Vec::new();
+        let mut new_paths = Vec::new();
+        for path in paths {
+            let result = if copy {
+                file_ops::copy_into(&path, dir)
+            } else {
+                file_ops::move_into(&path, dir)
+            };
+            match result {
+                Ok(new_path) => {
+                    if !copy && new_path != path {
+                        if let Some(editor) = &self.editor {
+                            editor.update(cx, |editor, cx| editor.file_moved(&path, &new_path, cx));
+                        }
+                        changed.push(path);
+                    }
+                    changed.push(new_path.clone());
+                    new_paths.push(new_path);
+                }
+                Err(err) => self.error = Some(err.to_string()),
             }
         }
 
-        false
+        self.paths_changed(changed, cx);
+        if let Some((last, rest)) = new_paths.split_last() {
+            self.select(last.clone());
+            self.marked.extend(rest.iter().cloned());
+        }
+        cx.notify();
+    }
+
+    /// Copy files dropped from outside the app into a directory
+    fn drop_external(&mut self, paths: &ExternalPaths, dir: &Path, cx: &mut Context<Self>) {
+        self.drag_hover = None;
+        self.error = None;
+        let mut copied = Vec::new();
+        for path in paths.paths() {
+            match file_ops::copy_into(path, dir) {
+                Ok(new_path) => copied.push(new_path),
+                Err(err) => self.error = Some(err.to_string()),
+            }
+        }
+        self.paths_changed(copied.iter().cloned(), cx);
+        if let Some((last, rest)) = copied.split_last() {
+            self.select(last.clone());
+            self.marked.extend(rest.iter().cloned());
+        }
+        cx.notify();
+    }
+
+    fn select_next(&mut self, _: &SelectNext, _window: &mut Window, cx: &mut Context<Self>) {
+        self.move_selection(1, cx);
+    }
+
+    fn select_previous(
+        &mut self,
+        _: &SelectPrevious,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.move_selection(-1, cx);
+    }
+
+    /// Move the cursor up or down the visible entries
+    fn move_selection(&mut self, delta: isize, cx: &mut Context<Self>) {
+        let visible = self.visible_paths();
+        let ix = match self
+            .selected
+            .as_ref()
+            .and_then(|selected| visible.iter().position(|path| path == selected))
+        {
+            Some(ix) => ix.saturating_add_signed(delta).min(visible.len() - 1),
+            None => 0,
+        };
+        self.select(visible[ix].clone());
+        self.scroll_to_selected();
+        cx.notify();
+    }
+
+    /// Collapse the selected directory, or move up to its parent
+    fn collapse_selected(
+        &mut self,
+        _: &CollapseSelected,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let Some(path) = self.selected.clone() else {
+            return;
+        };
+        let is_expanded = self
+            .entries
+            .entry_for_path(&path)
+            .is_some_and(|entry| entry.is_dir() && entry.is_expanded);
+        if is_expanded {
+            self.toggle_entry(&path, cx);
+        } else if path != self.root_path {
+            if let Some(parent) = path.parent() {
+                self.select(parent.to_path_buf());
+                self.scroll_to_selected();
+            }
+        }
+        cx.notify();
+    }
+
+    /// Expand the selected directory, or move into it if it's already expanded
+    fn expand_selected(
+        &mut self,
+        _: &ExpandSelected,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let Some(path) = self.selected.clone() else {
+            return;
+        };
+        let Some(entry) = self.entries.entry_for_path(&path) else {
+            return;
+        };
+        if !entry.can_expand(self.follow_symlinks) {
+            return;
+        }
+        if !entry.is_expanded {
+            self.toggle_entry(&path, cx);
+        } else if let Some(first) = entry
+            .children
+            .iter()
+            .filter_map(|child| self.entries.get(*child))
+            .find(|child| self.show_hidden || !child.is_hidden())
+        {
+            let first = first.path.clone();
+            self.select(first);
+            self.scroll_to_selected();
+        }
+        cx.notify();
+    }
+
+    /// Open the selected file and move focus to the editor, or toggle the selected directory
+    fn open_selected(&mut self, _: &OpenSelected, window: &mut Window, cx: &mut Context<Self>) {
+        let Some(path) = self.selected.clone() else {
+            return;
+        };
+        if self.activate(&path, cx) {
+            if let Some(editor) = &self.editor {
+                let focus_handle = editor.read(cx).focus_handle(cx);
+                window.focus(&focus_handle, cx);
+            }
+        }
+        cx.notify();
+    }
+
+    /// Open the selected file while keeping focus in the tree
+    fn preview_selected(
+        &mut self,
+        _: &PreviewSelected,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        if let Some(path) = self.selected.clone() {
+            self.activate(&path, cx);
+            cx.notify();
+        }
+    }
+
+    /// Jump to the next entry whose name starts with what's been typed
+    fn type_ahead(&mut self, event: &KeyDownEvent, _window: &mut Window, cx: &mut Context<Self>) {
+        if self.name_editor.is_some() {
+            return;
+        }
+        let modifiers = &event.keystroke.modifiers;
+        if modifiers.control || modifiers.platform || modifiers.alt || modifiers.function {
+            return;
+        }
+        let Some(typed) = event.keystroke.key_char.as_deref() else {
+            return;
+        };
+        if typed.trim().is_empty() || typed.chars().any(char::is_control) {
+            return;
+        }
+
+        let now = Instant::now();
+        if self
+            .last_typed_at
+            .is_none_or(|at| now - at > TYPE_AHEAD_TIMEOUT)
+        {
+            self.type_ahead.clear();
+        }
+        self.last_typed_at = Some(now);
+        self.type_ahead.push_str(&typed.to_lowercase());
+
+        // Searching from the cursor keeps the current match while more letters are typed
+        let visible = self.visible_paths();
+        let start = self
+            .selected
+            .as_ref()
+            .and_then(|selected| visible.iter().position(|path| path == selected))
+            .unwrap_or(0);
+        let found = visible[start..]
+            .iter()
+            .chain(&visible[..start])
+            .find(|path| {
+                path.file_name().is_some_and(|name| {
+                    name.to_string_lossy()
+                        .to_lowercase()
+                        .starts_with(&self.type_ahead)
+                })
+            })
+            .cloned();
+        if let Some(path) = found {
+            self.select(path);
+            self.scroll_to_selected();
+            cx.notify();
+        }
+        cx.stop_propagation();
+    }
+
+    fn toggle_hidden_files(
+        &mut self,
+        _: &ToggleHiddenFiles,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.show_hidden = !self.show_hidden;
+        self.update_rows();
+        cx.notify();
+    }
+
+    fn toggle_follow_symlinks(
+        &mut self,
+        _: &ToggleFollowSymlinks,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.set_follow_symlinks(!self.follow_symlinks, cx);
+    }
+
+    /// Read every loaded directory again, keeping expanded directories expanded
+    pub fn refresh(&mut self, cx: &mut Context<Self>) {
+        self.ignores.invalidate_all();
+        let ignores = IgnoreStack::for_dir(self.ignores.clone(), &self.root_path, &self.root_path);
+        self.entries.rescan_loaded(self.entries.root(), &ignores);
+        self.update_rows();
+        self.clear_missing_selection();
+        self.rebuild_index(cx);
+        cx.notify();
+    }
+
+    fn toggle_entry(&mut self, path: &PathBuf, cx: &mut Context<Self>) {
+        let Some(entry) = self.entries.entry_for_path_mut(path) else {
+            return;
+        };
+        entry.is_expanded = !entry.is_expanded;
+        // A directory's contents are read the first time it's expanded
+        if entry.is_expanded {
+            self.load_children(path.clone(), cx);
+        }
+        self.update_rows();
+        cx.notify();
+    }
+
+    /// Buttons for the file operations, acting on the selected entry
+    fn render_toolbar(&self, cx: &mut Context<Self>) -> Div {
+        let button = |label: &'static str| {
+            div()
+                .px_2()
+                .py_1()
+                .text_xs()
+                .rounded_md()
+                .cursor_pointer()
+                .hover(|style| style.bg(rgb(0xf0f0f0)))
+                .child(label)
+        };
+        let has_selection = self.selected.is_some();
+
+        div()
+            .flex()
+            .gap_1()
+            .px_1()
+            .py_1()
+            .border_b_1()
+            .border_color(rgb(0xe0e0e0))
+            .child(button("New File").on_mouse_down(
+                MouseButton::Left,
+                cx.listener(|this, _event: &MouseDownEvent, window, cx| {
+                    this.new_file(&NewFile, window, cx)
+                }),
+            ))
+            .child(button("New Folder").on_mouse_down(
+                MouseButton::Left,
+                cx.listener(|this, _event: &MouseDownEvent, window, cx| {
+                    this.new_folder(&NewFolder, window, cx)
+                }),
+            ))
+            .when(has_selection, |toolbar| {
+                toolbar
+                    .child(button("Rename").on_mouse_down(
+                        MouseButton::Left,
+                        cx.listener(|this, _event: &MouseDownEvent, window, cx| {
+                            this.rename(&Rename, window, cx)
+                        }),
+                    ))
+                    .child(button("Duplicate").on_mouse_down(
+                        MouseButton::Left,
+                        cx.listener(|this, _event: &MouseDownEvent, window, cx| {
+                            this.duplicate(&Duplicate, window, cx)
+                        }),
+                    ))
+                    .child(button("Trash").on_mouse_down(
+                        MouseButton::Left,
+                        cx.listener(|this, _event: &MouseDownEvent, window, cx| {
+                            this.move_to_trash(&MoveToTrash, window, cx)
+                        }),
+                    ))
+            })
+            .when(!self.trashed.is_empty(), |toolbar| {
+                toolbar.child(button("Undo Trash").on_mouse_down(
+                    MouseButton::Left,
+                    cx.listener(|this, _event: &MouseDownEvent, window, cx| {
+                        this.undo_move_to_trash(&UndoMoveToTrash, window, cx)
+                    }),
+                ))
+            })
     }
 
-    fn render_entry(&self, entry: &Entry, depth: usize, cx: &mut Context<Self>) -> Div {
+    fn render_row(&self, ix: usize, has_focus: bool, cx: &mut Context<Self>) -> AnyElement {
+        let (id, depth) = match self.rows[ix] {
+            Row::Entry { id, depth } => (id, depth),
+            Row::NewEntry { depth } => return self.render_new_entry_row(depth),
+        };
+        let Some(entry) = self.entries.get(id) else {
+            return div().h(ROW_HEIGHT).into_any_element();
+        };
         let path = entry.path.clone();
         let indent = depth * 20;
-        let is_dir = matches!(entry.kind, EntryKind::Directory);
+        let is_dir = entry.is_dir();
+        let can_expand = entry.can_expand(self.follow_symlinks);
         let is_expanded = entry.is_expanded;
+        let is_selected = self.is_selected(&entry.path);
+        let is_cursor = has_focus && self.selected.as_ref() == Some(&entry.path);
+        let is_loading = entry.load_state == LoadState::Loading;
+        let is_ignored = entry.is_ignored;
+        let is_root = entry.path == self.root_path;
+        let git_status = self.git_statuses.status(&entry.path, is_dir);
+        // Things dropped on a file land in the folder beside it
+        let drop_dir = if is_dir {
+            entry.path.clone()
+        } else {
+            entry
+                .path
+                .parent()
+                .map_or_else(|| self.root_path.clone(), Path::to_path_buf)
+        };
+        // Dragging a selected entry takes the rest of the selection with it
+        let dragged = DraggedEntry {
+            path: entry.path.clone(),
+            name: if is_selected && !self.marked.is_empty() {
+                format!("{} items", self.marked.len() + 1).into()
+            } else {
+                entry.name.clone().into()
+            },
+        };
+        let rename_input =
+            self.name_editor
+                .as_ref()
+                .and_then(|name_editor| match &name_editor.edit {
+                    NameEdit::Rename { path } if *path == entry.path => {
+                        Some(name_editor.input.clone())
+                    }
+                    _ => None,
+                });
+        let is_renaming = rename_input.is_some();
+        let (link_target, is_broken) = match &entry.kind {
+            EntryKind::Symlink {
+                target, is_broken, ..
+            } => (Some(target.display().to_string()), *is_broken),
+            _ => (None, false),
+        };
 
         let icon = if is_dir {
             if is_expanded {
@@ -172,46 +1510,238 @@ impl Worktree {
             "📄"
         };
 
-        let mut container = div().flex().flex_col().child(
-            div()
-                .flex()
-                .items_center()
-                .px_2()
-                .py_1()
-                .pl(px(indent as f32))
-                .hover(|style| style.bg(rgb(0xf0f0f0)))
-                .cursor_pointer()
-                .on_mouse_down(
-                    MouseButton::Left,
-                    cx.listener(move |this, _event: &MouseDownEvent, _window, cx| {
-                        if is_dir {
-                            this.toggle_entry(&path, cx);
-                        } else if let Some(editor) = &this.editor {
-                            let path_clone = path.clone();
-                            editor.update(cx, |ed, cx| {
-                                ed.open_file(path_clone, cx);
-                            });
-                        }
-                    }),
-                )
-                .child(div().w(px(24.)).text_sm().child(icon))
-                .child(
+        div()
+            .id(id)
+            .h(ROW_HEIGHT)
+            .flex()
+            .items_center()
+            .px_2()
+            .py_1()
+            .pl(px(indent as f32))
+            .border_1()
+            .border_color(transparent_black())
+            .when(is_selected, |div| div.bg(rgb(0xe0ecff)))
+            .when(!is_selected, |div| {
+                div.hover(|style| style.bg(rgb(0xf0f0f0)))
+            })
+            // The cursor is outlined while the tree has focus
+            .when(is_cursor, |div| div.border_color(rgb(0x0066ff)))
+            // Ignored entries are only listed when hidden files are shown
+            .when(is_ignored, |div| div.opacity(0.5))
+            .cursor_pointer()
+            .when(!is_root, |row| {
+                row.on_drag(dragged, |dragged, _offset, _window, cx| {
+                    cx.new(|_| dragged.clone())
+                })
+            })
+            .drag_over::<DraggedEntry>(|style, _, _, _| style.bg(rgb(0xd0e4ff)))
+            .drag_over::<ExternalPaths>(|style, _, _, _| style.bg(rgb(0xd0e4ff)))
+            .when(can_expand && !is_expanded, |row| {
+                let entry_path = entry.path.clone();
+                let external_path = entry.path.clone();
+                row.on_drag_move(cx.listener(
+                    move |this, event: &DragMoveEvent<DraggedEntry>, _window, cx| {
+                        this.drag_moved(&entry_path, event.bounds, event.event.position, cx)
+                    },
+                ))
+                .on_drag_move(cx.listener(
+                    move |this, event: &DragMoveEvent<ExternalPaths>, _window, cx| {
+                        this.drag_moved(&external_path, event.bounds, event.event.position, cx)
+                    },
+                ))
+            })
+            .on_drop(cx.listener({
+                let drop_dir = drop_dir.clone();
+                move |this, dragged: &DraggedEntry, window, cx| {
+                    this.drop_entry(dragged, &drop_dir, window, cx)
+                }
+            }))
+            .on_drop(
+                cx.listener(move |this, paths: &ExternalPaths, _window, cx| {
+                    this.drop_external(paths, &drop_dir, cx)
+                }),
+            )
+            .on_mouse_down(
+                MouseButton::Left,
+                cx.listener(move |this, event: &MouseDownEvent, window, cx| {
+                    // Clicks in the rename input are the input's own
+                    if is_renaming {
+                        return;
+                    }
+                    window.focus(&this.focus_handle, cx);
+                    if event.modifiers.shift {
+                        this.select_range(&path);
+                    } else if event.modifiers.control || event.modifiers.platform {
+                        this.toggle_selected(&path);
+                    } else {
+                        this.select(path.clone());
+                        this.activate(&path, cx);
+                    }
+                    cx.notify();
+                }),
+            )
+            .child(div().w(px(24.)).text_sm().child(icon))
+            .map(|row| match rename_input {
+                // Renaming swaps the name for an input holding it
+                Some(input) => row.child(div().flex_1().text_sm().child(input)),
+                None => row.child(
                     div()
                         .text_sm()
-                        .text_color(if is_dir { rgb(0x0066cc) } else { rgb(0x333333) })
+                        .text_color(match git_status {
+                            Some(status) => status.color(),
+                            None if is_dir => rgb(0x0066cc),
+                            None => rgb(0x333333),
+                        })
                         .when(is_dir, |div| div.font_weight(FontWeight::BOLD))
                         .child(entry.name.clone()),
                 ),
-        );
+            })
+            .when_some(link_target, |row, target| {
+                row.child(
+                    div()
+                        .ml_2()
+                        .text_xs()
+                        .text_color(rgb(0x999999))
+                        .child(format!("→ {}", target)),
+                )
+            })
+            .when(is_broken, |row| {
+                row.child(
+                    div()
+                        .ml_2()
+                        .text_xs()
+                        .text_color(rgb(0xcc0000))
+                        .child("broken link"),
+                )
+            })
+            .when(is_loading, |row| {
+                row.child(
+                    div()
+                        .ml_2()
+                        .text_xs()
+                        .text_color(rgb(0x999999))
+                        .child("Loading…"),
+                )
+            })
+            .when(entry.load_state == LoadState::Cycle, |row| {
+                row.child(
+                    div()
+                        .ml_2()
+                        .text_xs()
+                        .text_color(rgb(0x999999))
+                        .child("links to a parent folder"),
+                )
+            })
+            // Files get their status letter, while folders show a dot for changes inside
+            .when_some(git_status, |row, status| {
+                row.child(
+                    div()
+                        .ml_auto()
+                        .pl_2()
+                        .text_xs()
+                        .font_weight(FontWeight::BOLD)
+                        .text_color(status.color())
+                        .child(if is_dir { "•" } else { status.letter() }),
+                )
+            })
+            .into_any_element()
+    }
 
-        // Render children if directory is expanded
-        if is_dir && is_expanded {
-            for child in &entry.children {
-                container = container.child(self.render_entry(child, depth + 1, cx));
-            }
+    /// The input a new file or folder is named in
+    fn render_new_entry_row(&self, depth: usize) -> AnyElement {
+        let Some(name_editor) = &self.name_editor else {
+            return div().h(ROW_HEIGHT).into_any_element();
+        };
+        let icon = match name_editor.edit {
+            NameEdit::NewFolder { .. } => "▶",
+            _ => "📄",
+        };
+        div()
+            .h(ROW_HEIGHT)
+            .flex()
+            .items_center()
+            .px_2()
+            .pl(px((depth * 20) as f32))
+            .child(div().w(px(24.)).text_sm().child(icon))
+            .child(div().flex_1().text_sm().child(name_editor.input.clone()))
+            .into_any_element()
+    }
+
+    /// Where the scrollbar goes, from how far the list scrolled when last drawn
+    fn scrollbar(&self) -> Option<ScrollbarLayout> {
+        if self.rows.is_empty() {
+            return None;
+        }
+        let scroll = self.list_scroll.0.get();
+        let bounds = scroll.viewport;
+        let max_y = scroll.content_height - bounds.size.height;
+        if max_y <= px(0.) {
+            return None;
         }
+        let track = Bounds::new(
+            point(bounds.right() - SCROLLBAR_WIDTH, bounds.top()),
+            size(SCROLLBAR_WIDTH, bounds.size.height),
+        );
+        Some(ScrollbarLayout::new(
+            track,
+            scroll.content_height,
+            scroll.scroll_y,
+            max_y,
+        ))
+    }
+
+    /// Grab the thumb where it was clicked, or center it on the click when the track was hit
+    fn start_scrollbar_drag(&mut self, position: Point<Pixels>, cx: &mut Context<Self>) {
+        let Some(scrollbar) = self.scrollbar() else {
+            return;
+        };
+        let grab = if scrollbar.thumb.contains(&position) {
+            position.y - scrollbar.thumb.top()
+        } else {
+            scrollbar.thumb.size.height / 2.
+        };
+        self.scrollbar_drag = Some(grab);
+        self.drag_scrollbar(position.y - grab, cx);
+    }
+
+    /// Scroll to the row nearest where the thumb was dragged
+    fn drag_scrollbar(&mut self, thumb_top: Pixels, cx: &mut Context<Self>) {
+        let Some(scrollbar) = self.scrollbar() else {
+            return;
+        };
+        let scroll = self.list_scroll.0.get();
+        let max_y = scroll.content_height - scroll.viewport.size.height;
+        let scroll_y = scrollbar.scroll_y_for_thumb_top(thumb_top, max_y);
+        let row = (scroll_y / ROW_HEIGHT).round() as usize;
+        self.scroll_handle
+            .scroll_to_item_strict(row, ScrollStrategy::Top);
+        cx.notify();
+    }
 
-        container
+    fn render_scrollbar(&self, scrollbar: ScrollbarLayout, cx: &mut Context<Self>) -> Div {
+        div()
+            .absolute()
+            .top_0()
+            .right_0()
+            .h_full()
+            .w(SCROLLBAR_WIDTH)
+            .bg(rgb(0xf0f0f0))
+            .occlude()
+            .on_mouse_down(
+                MouseButton::Left,
+                cx.listener(|this, event: &MouseDownEvent, _window, cx| {
+                    this.start_scrollbar_drag(event.position, cx)
+                }),
+            )
+            .child(
+                div()
+                    .absolute()
+                    .top(scrollbar.thumb.top() - scrollbar.track.top())
+                    .w_full()
+                    .h(scrollbar.thumb.size.height)
+                    .rounded(px(4.))
+                    .bg(rgb(0xc1c1c1)),
+            )
     }
 }
 
@@ -222,14 +1752,95 @@ impl Focusable for Worktree {
 }
 
 impl Render for Worktree {
-    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+    fn render(&mut self, window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        // Navigation keys are left to the name input while one is open
+        let mut key_context = KeyContext::new_with_defaults();
+        key_context.add("Worktree");
+        if self.name_editor.is_none() {
+            key_context.add("not_editing");
+        }
+        let has_focus = self.focus_handle.contains_focused(window, cx);
+        let scrollbar = self.scrollbar();
+
         div()
             .flex()
             .flex_col()
             .bg(white())
             .overflow_hidden()
             .h_full()
+            .key_context(key_context)
             .track_focus(&self.focus_handle)
-            .child(self.render_entry(&self.root.clone(), 0, cx))
+            .on_key_down(cx.listener(Self::type_ahead))
+            .on_action(cx.listener(Self::select_next))
+            .on_action(cx.listener(Self::select_previous))
+            .on_action(cx.listener(Self::collapse_selected))
+            .on_action(cx.listener(Self::expand_selected))
+            .on_action(cx.listener(Self::open_selected))
+            .on_action(cx.listener(Self::preview_selected))
+            .on_action(cx.listener(Self::toggle_hidden_files))
+            .on_action(cx.listener(Self::toggle_follow_symlinks))
+            .on_action(cx.listener(Self::new_file))
+            .on_action(cx.listener(Self::new_folder))
+            .on_action(cx.listener(Self::rename))
+            .on_action(cx.listener(Self::duplicate))
+            .on_action(cx.listener(Self::move_to_trash))
+            .on_action(cx.listener(Self::undo_move_to_trash))
+            .on_action(cx.listener(Self::copy_path))
+            .on_action(cx.listener(Self::copy_relative_path))
+            // Drops below the last entry go in the root
+            .on_drop(cx.listener(|this, dragged: &DraggedEntry, window, cx| {
+                let root = this.root_path.clone();
+                this.drop_entry(dragged, &root, window, cx)
+            }))
+            .on_drop(cx.listener(|this, paths: &ExternalPaths, _window, cx| {
+                let root = this.root_path.clone();
+                this.drop_external(paths, &root, cx)
+            }))
+            .child(self.render_toolbar(cx))
+            .when_some(self.error.clone(), |worktree, error| {
+                worktree.child(
+                    div()
+                        .px_2()
+                        .py_1()
+                        .text_xs()
+                        .text_color(rgb(0xcc0000))
+                        .child(error),
+                )
+            })
+            .on_mouse_move(cx.listener(|this, event: &MouseMoveEvent, _window, cx| {
+                if let Some(grab) = this.scrollbar_drag {
+                    if event.pressed_button == Some(MouseButton::Left) {
+                        this.drag_scrollbar(event.position.y - grab, cx);
+                    }
+                }
+            }))
+            .on_mouse_up(
+                MouseButton::Left,
+                cx.listener(|this, _event: &MouseUpEvent, _window, _cx| {
+                    this.scrollbar_drag = None;
+                }),
+            )
+            .child(
+                div()
+                    .relative()
+                    .flex_1()
+                    .child(
+                        uniform_list(
+                            "worktree-entries",
+                            self.rows.len(),
+                            cx.processor(move |this, range: Range<usize>, _window, cx| {
+                                range
+                                    .map(|ix| this.render_row(ix, has_focus, cx))
+                                    .collect::<Vec<_>>()
+                            }),
+                        )
+                        .track_scroll(self.scroll_handle.clone())
+                        .with_decoration(self.list_scroll.clone())
+                        .size_full(),
+                    )
+                    .when_some(scrollbar, |list, scrollbar| {
+                        list.child(self.render_scrollbar(scrollbar, cx))
+                    }),
+            )
     }
 }
@@ -4,6 +4,11 @@ mod components;
 mod views;
 
 use components::text_editor::*;
+use components::worktree::{
+    CollapseSelected, CopyPath, CopyRelativePath, Duplicate, ExpandSelected, MoveToTrash, NewFile,
+    NewFolder, OpenSelected, PreviewSelected, Rename, SelectNext, SelectPrevious,
+    ToggleFollowSymlinks, ToggleHiddenFiles, UndoMoveToTrash,
+};
 use views::HelloWorld;
 
 fn main() {
@@ -14,8 +19,51 @@ fn main() {
             KeyBinding::new("delete", Delete, Some("TextEditor")),
             KeyBinding::new("left", MoveLeft, Some("TextEditor")),
             KeyBinding::new("right", MoveRight, Some("TextEditor")),
-            KeyBinding::new("home", MoveToStart, Some("TextEditor")),
-            KeyBinding::new("end", MoveToEnd, Some("TextEditor")),
+            KeyBinding::new("alt-left", MoveToPreviousWordStart, Some("TextEditor")),
+            KeyBinding::new("alt-right", MoveToNextWordEnd, Some("TextEditor")),
+            KeyBinding::new(
+                "alt-backspace",
+                DeleteToPreviousWordStart,
+                Some("TextEditor"),
+            ),
+            KeyBinding::new("alt-delete", DeleteToNextWordEnd, Some("TextEditor")),
+            KeyBinding::new("up", MoveUp, Some("TextEditor")),
+            KeyBinding::new("down", MoveDown, Some("TextEditor")),
+            KeyBinding::new("home", MoveToBeginningOfLine, Some("TextEditor")),
+            KeyBinding::new("end", MoveToEndOfLine, Some("TextEditor")),
+            KeyBinding::new("cmd-left", MoveToBeginningOfLine, Some("TextEditor")),
+            KeyBinding::new("cmd-right", MoveToEndOfLine, Some("TextEditor")),
+            KeyBinding::new("cmd-up", MoveToBeginningOfDocument, Some("TextEditor")),
+            KeyBinding::new("cmd-down", MoveToEndOfDocument, Some("TextEditor")),
+            KeyBinding::new("ctrl-home", MoveToBeginningOfDocument, Some("TextEditor")),
+            KeyBinding::new("ctrl-end", MoveToEndOfDocument, Some("TextEditor")),
+            KeyBinding::new("pageup", PageUp, Some("TextEditor")),
+            KeyBinding::new("pagedown", PageDown, Some("TextEditor")),
+            KeyBinding::new("shift-left", SelectLeft, Some("TextEditor")),
+            KeyBinding::new("shift-right", SelectRight, Some("TextEditor")),
+            KeyBinding::new(
+                "alt-shift-left",
+                SelectToPreviousWordStart,
+                Some("TextEditor"),
+            ),
+            KeyBinding::new("alt-shift-right", SelectToNextWordEnd, Some("TextEditor")),
+            KeyBinding::new("shift-up", SelectUp, Some("TextEditor")),
+            KeyBinding::new("shift-down", SelectDown, Some("TextEditor")),
+            KeyBinding::new("shift-home", SelectToBeginningOfLine, Some("TextEditor")),
+            KeyBinding::new("shift-end", SelectToEndOfLine, Some("TextEditor")),
+            KeyBinding::new(
+                "cmd-shift-left",
+                SelectToBeginningOfLine,
+                Some("TextEditor"),
+            ),
+            KeyBinding::new("cmd-shift-right", SelectToEndOfLine, Some("TextEditor")),
+            KeyBinding::new(
+                "cmd-shift-up",
+                SelectToBeginningOfDocument,
+                Some("TextEditor"),
+            ),
+            KeyBinding::new("cmd-shift-down", SelectToEndOfDocument, Some("TextEditor")),
+            KeyBinding::new("cmd-a", SelectAll, Some("TextEditor")),
             KeyBinding::new("enter", Newline, Some("TextEditor")),
             KeyBinding::new("cmd-v", Paste, Some("TextEditor")),
             KeyBinding::new("cmd-c", Copy, Some("TextEditor")),
@@ -23,6 +71,31 @@ fn main() {
             KeyBinding::new("cmd-s", Save, Some("TextEditor")),
             KeyBinding::new("cmd-z", Undo, Some("TextEditor")),
             KeyBinding::new("cmd-shift-z", Redo, Some("TextEditor")),
+            KeyBinding::new("cmd-shift-l", CycleLineNumbers, Some("TextEditor")),
+            KeyBinding::new("cmd-shift-.", ToggleHiddenFiles, Some("Worktree")),
+            KeyBinding::new("cmd-alt-l", ToggleFollowSymlinks, Some("Worktree")),
+            KeyBinding::new("cmd-n", NewFile, Some("Worktree && not_editing")),
+            KeyBinding::new("cmd-shift-n", NewFolder, Some("Worktree && not_editing")),
+            KeyBinding::new("f2", Rename, Some("Worktree && not_editing")),
+            KeyBinding::new("cmd-d", Duplicate, Some("Worktree && not_editing")),
+            KeyBinding::new(
+                "cmd-backspace",
+                MoveToTrash,
+                Some("Worktree && not_editing"),
+            ),
+            KeyBinding::new("cmd-z", UndoMoveToTrash, Some("Worktree && not_editing")),
+            KeyBinding::new("cmd-alt-c", CopyPath, Some("Worktree && not_editing")),
+            KeyBinding::new(
+                "cmd-alt-shift-c",
+                CopyRelativePath,
+                Some("Worktree && not_editing"),
+            ),
+            KeyBinding::new("down", SelectNext, Some("Worktree && not_editing")),
+            KeyBinding::new("up", SelectPrevious, Some("Worktree && not_editing")),
+            KeyBinding::new("left", CollapseSelected, Some("Worktree && not_editing")),
+            KeyBinding::new("right", ExpandSelected, Some("Worktree && not_editing")),
+            KeyBinding::new("enter", OpenSelected, Some("Worktree && not_editing")),
+            KeyBinding::new("space", PreviewSelected, Some("Worktree && not_editing")),
         ]);
 
         cx.open_window(WindowOptions::default(), |_, cx| {
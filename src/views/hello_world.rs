@@ -1,4 +1,4 @@
-use crate::components::{TextEditor, TextInput, Worktree};
+use crate::components::{HistoryPanel, HistoryStore, Settings, TextEditor, TextInput, Worktree};
 use gpui::*;
 
 pub struct HelloWorld {
@@ -6,6 +6,7 @@ pub struct HelloWorld {
     text_input: Entity<TextInput>,
     styled_input: Entity<TextInput>,
     text_editor: Entity<TextEditor>,
+    history_panel: Entity<HistoryPanel>,
     worktree: Option<Entity<Worktree>>,
 }
 
@@ -14,13 +15,20 @@ impl HelloWorld {
         let text_input = cx.new(|cx| TextInput::new(cx));
         let styled_input = cx.new(|cx| TextInput::new(cx).placeholder("Enter your name..."));
 
+        let settings = Settings::load();
+
         let text_editor = cx.new(|cx| {
             TextEditor::with_text(
                 "// Click a file in the worktree to open it\n// Cmd+S to save",
                 cx,
             )
+            .subword_navigation(settings.subword_navigation)
+            .line_numbers(settings.line_numbers)
+            .keep_backup(settings.keep_backup)
         });
 
+        let history_panel = cx.new(|cx| HistoryPanel::new(text_editor.clone(), cx));
+
         // Load the current project directory as worktree
         let worktree = cx.new(|cx| {
             Worktree::new(".", cx).unwrap_or_else(|_| {
@@ -29,15 +37,27 @@ impl HelloWorld {
         });
 
         // Set the editor reference in the worktree so it can open files
-        worktree.update(cx, |tree, _cx| {
+        worktree.update(cx, |tree, cx| {
             tree.set_editor(text_editor.clone());
+            if !settings.exclude_globs.is_empty() {
+                tree.set_exclude_globs(&settings.exclude_globs, cx);
+            }
+            if !settings.follow_symlinks {
+                tree.set_follow_symlinks(false, cx);
+            }
         });
 
+        // Keep undo history for the worktree's files between sessions
+        if let Some(store) = HistoryStore::new(worktree.read(cx).root_path()) {
+            text_editor.update(cx, |editor, _cx| editor.set_history_store(store));
+        }
+
         Self {
             text,
             text_input,
             styled_input,
             text_editor,
+            history_panel,
             worktree: Some(worktree),
         }
     }
@@ -60,7 +80,7 @@ impl Render for HelloWorld {
             );
         }
 
-        // Add text editor on the right
+        // Add text editor in the middle
         root.child(
             div()
                 .flex_1()
@@ -68,6 +88,16 @@ impl Render for HelloWorld {
                 .p_4()
                 .child(self.text_editor.clone()),
         )
+        // Add undo history on the right
+        .child(
+            div()
+                .w(px(280.))
+                .h_full()
+                .border_l_1()
+                .border_color(rgb(0xe5e5e5))
+                .overflow_hidden()
+                .child(self.history_panel.clone()),
+        )
     }
 }
 